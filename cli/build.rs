@@ -0,0 +1,134 @@
+// Copyright (c) 2023 The Nimbus Authors. All rights reserved.
+//
+// The use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+// Points at a prebuilt runtime for the host platform. When unset, the runtime is built
+// from the sources in this workspace. Thus, it is required when the CLI crate is vendored
+// or packaged on its own.
+const HOST_RUNTIME_ENV: &str = "KICKOFF_HOST_RUNTIME";
+
+// Generates the "RUNTIMES" map when building with Cargo. Bazel builds the runtimes for
+// every supported target on its own and enables the "bazel" feature instead. Thus, this
+// script does nothing in that case.
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-env-changed={}", HOST_RUNTIME_ENV);
+
+    if env::var_os("CARGO_FEATURE_BAZEL").is_some() {
+        return;
+    }
+
+    let out_dir = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+
+    let arch = env::var("CARGO_CFG_TARGET_ARCH").unwrap();
+    let os = env::var("CARGO_CFG_TARGET_OS").unwrap();
+
+    let entries = match (
+        env::var_os("CARGO_FEATURE_EMBEDDED_RUNTIME"),
+        host_target(&arch, &os),
+    ) {
+        (Some(_), Some(name)) => {
+            let path = match env::var_os(HOST_RUNTIME_ENV) {
+                Some(path) => PathBuf::from(path),
+                None => build_runtime(&out_dir, &os),
+            };
+
+            format!(
+                "    {:?} => include_bytes!({:?}),\n",
+                name,
+                path.to_str().expect("non UTF-8 runtime path")
+            )
+        }
+        // Runtimes can still be given via "--runtime" or "--runtime-dir".
+        (Some(_), None) => {
+            println!(
+                "cargo:warning=No runtime is embedded for the unsupported host platform: {}-{}",
+                arch, os
+            );
+            String::new()
+        }
+        (None, _) => String::new(),
+    };
+
+    let runtimes = format!(
+        "static RUNTIMES: phf::Map<&'static str, &'static [u8]> = phf_map! {{\n{}}};\n",
+        entries
+    );
+
+    fs::write(out_dir.join("runtimes.rs"), runtimes).unwrap();
+}
+
+// Mirrors "HOST_PLATFORMS" in "src/main.rs".
+fn host_target(arch: &str, os: &str) -> Option<&'static str> {
+    match (arch, os) {
+        ("aarch64", "linux") => Some("aarch64-unknown-linux-gnu"),
+        ("aarch64", "macos") => Some("aarch64-apple-macos-none"),
+        ("x86_64", "linux") => Some("x86_64-unknown-linux-gnu"),
+        ("x86_64", "macos") => Some("x86_64-apple-macos-none"),
+        ("x86_64", "windows") => Some("x86_64-pc-windows-gnu"),
+        _ => None,
+    }
+}
+
+fn build_runtime(out_dir: &Path, os: &str) -> PathBuf {
+    let workspace = PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").unwrap()).join("..");
+
+    // The runtime sources are only around when building from this workspace (i.e. not
+    // when the CLI crate is vendored or packaged on its own).
+    if !workspace.join("runtime").join("Cargo.toml").is_file() {
+        panic!(
+            "the runtime sources are not available at {}. Set {} to the path of a prebuilt \
+             runtime for the host platform, or disable the \"embedded-runtime\" feature",
+            workspace.join("runtime").display(),
+            HOST_RUNTIME_ENV
+        );
+    }
+
+    for path in ["Cargo.toml", "src", "runtime/Cargo.toml", "runtime/src"] {
+        println!("cargo:rerun-if-changed={}", workspace.join(path).display());
+    }
+
+    let target = env::var("TARGET").unwrap();
+    let target_dir = out_dir.join("runtime");
+
+    // A separate target directory keeps this build from waiting on the lock held by the
+    // one running this script.
+    let status = Command::new(env::var_os("CARGO").unwrap_or_else(|| "cargo".into()))
+        .args([
+            "build",
+            "--release",
+            "--package",
+            "runtime",
+            "--target",
+            &target,
+        ])
+        .arg("--manifest-path")
+        .arg(workspace.join("runtime").join("Cargo.toml"))
+        .arg("--target-dir")
+        .arg(&target_dir)
+        // Keeps "cargo clippy" on the CLI from linting the runtime as well.
+        .env_remove("RUSTC_WORKSPACE_WRAPPER")
+        .status()
+        .expect("failed to run cargo");
+
+    if !status.success() {
+        panic!("failed to build the runtime for target: {}", target);
+    }
+
+    let suffix = match os {
+        "windows" => ".exe",
+        _ => "",
+    };
+
+    target_dir
+        .join(target)
+        .join("release")
+        .join(format!("runtime{}", suffix))
+}
@@ -0,0 +1,117 @@
+// Copyright (c) 2023 The Nimbus Authors. All rights reserved.
+//
+// The use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+use std::{
+    process::ExitCode,
+    time::{Duration, SystemTime},
+};
+
+use clap::{Args, Subcommand};
+
+use kickoff::cache::{Cache, Entry};
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Manage the cache where launchers extract their embedded programs.
+#[derive(Debug, Args)]
+pub struct Command {
+    #[command(subcommand)]
+    cmd: Commands,
+}
+
+#[derive(Debug, Subcommand)]
+enum Commands {
+    Gc(GcCommand),
+    List(ListCommand),
+}
+
+/// Remove the cache entries that have not been used recently.
+#[derive(Debug, Args)]
+struct GcCommand {
+    /// Remove the entries not used in the last <DAYS> days.
+    #[arg(long, default_value_t = 30)]
+    days: u64,
+}
+
+/// List the cache entries.
+#[derive(Debug, Args)]
+struct ListCommand {}
+
+impl Command {
+    pub fn execute(&self) -> ExitCode {
+        let cache = match Cache::user() {
+            Some(c) => c,
+            None => {
+                eprintln!("[ERROR] Failed to determine the cache directory");
+                return ExitCode::from(1);
+            }
+        };
+
+        match &self.cmd {
+            Commands::Gc(cmd) => cmd.execute(&cache),
+            Commands::List(cmd) => cmd.execute(&cache),
+        }
+    }
+}
+
+impl GcCommand {
+    fn execute(&self, cache: &Cache) -> ExitCode {
+        match cache.gc(Duration::from_secs(self.days * DAY.as_secs())) {
+            Ok(collected) => {
+                for entry in &collected.removed {
+                    println!("Removed {}", describe(entry));
+                }
+                println!(
+                    "Removed {} entries ({} bytes)",
+                    collected.removed.len(),
+                    collected.removed.iter().map(|e| e.size).sum::<u64>()
+                );
+
+                for (entry, err) in &collected.failed {
+                    eprintln!("[ERROR] Failed to remove {}: {}", entry.digest, err);
+                }
+
+                match collected.failed.is_empty() {
+                    true => ExitCode::from(0),
+                    false => ExitCode::from(1),
+                }
+            }
+            Err(err) => {
+                eprintln!("[ERROR] Failed to clean up the cache: {}", err);
+                ExitCode::from(1)
+            }
+        }
+    }
+}
+
+impl ListCommand {
+    fn execute(&self, cache: &Cache) -> ExitCode {
+        match cache.entries() {
+            Ok(entries) => {
+                for entry in &entries {
+                    println!("{}", describe(entry));
+                }
+                ExitCode::from(0)
+            }
+            Err(err) => {
+                eprintln!("[ERROR] Failed to list the cache entries: {}", err);
+                ExitCode::from(1)
+            }
+        }
+    }
+}
+
+fn describe(entry: &Entry) -> String {
+    let age = SystemTime::now()
+        .duration_since(entry.last_used)
+        .unwrap_or_default();
+
+    format!(
+        "{}  {} bytes  last used {} days ago",
+        entry.digest,
+        entry.size,
+        age.as_secs() / DAY.as_secs()
+    )
+}
@@ -4,133 +4,631 @@
 // license that can be found in the LICENSE file.
 
 use std::{
-    fmt::Display,
     fs::{File, OpenOptions},
     io::Write,
+    path::Path,
     process::ExitCode,
-    str::FromStr,
 };
 
-use clap::Args;
+use clap::{ArgGroup, Args};
 
-use kickoff::Manifest;
+use crate::{
+    manifest,
+    registry::{self, Registry, Runtime},
+    target::{self, Target},
+};
 
-#[allow(dead_code)]
-#[derive(Debug, Clone)]
-struct Target {
-    name: String,
-    runtime: &'static [u8],
-}
+use kickoff::{
+    seccomp::{self, Arch},
+    Manifest, ManifestBuilder, Namespace,
+};
 
-impl FromStr for Target {
-    type Err = String;
+const TARGET_PLACEHOLDER: &str = "{target}";
+const EXE_SUFFIX_PLACEHOLDER: &str = "{exe_suffix}";
 
-    fn from_str(target: &str) -> Result<Self, Self::Err> {
-        match super::RUNTIMES.get(target) {
-            Some(r) => Ok(Self {
-                name: target.to_string(),
-                runtime: r,
-            }),
-            None => Err(format!("unrecognized target: {}", target)),
-        }
-    }
-}
+/// Create a self-contained launcher for an arbitrary executable.
+#[derive(Debug, Args)]
+#[command(group(
+    ArgGroup::new("source")
+        .required(true)
+        .multiple(true)
+        .args(["manifest", "argv"]),
+))]
+pub struct Command {
+    /// The manifest file, or "-" to read it from the standard input. Settings given via
+    /// command line flags take precedence over the ones in this file.
+    #[arg(long)]
+    manifest: Option<String>,
 
-impl Default for Target {
-    fn default() -> Self {
-        let cpu = std::env::consts::ARCH;
-        let os = std::env::consts::OS;
+    /// The format of the manifest file. Guessed from its extension if not given.
+    #[arg(long = "manifest-format", value_enum, requires = "manifest")]
+    manifest_format: Option<manifest::Format>,
 
-        let name = super::HOST_PLATFORMS
-            .get(format!("{}-{}", cpu, os).as_str())
-            .expect(format!("unrecognized host platform: {}-{}", cpu, os).as_str());
+    /// Set the environment variable <KEY> to <VALUE>. Can be repeated.
+    #[arg(long = "env", value_name = "KEY=VALUE", value_parser = parse_env, help_heading = "Manifest")]
+    env: Vec<(String, String)>,
 
-        let runtime = super::RUNTIMES
-            .get(name)
-            .expect(format!("runtime not found for platform: {}", name).as_str());
+    /// Run the program from the working directory <CWD>.
+    #[arg(long, help_heading = "Manifest")]
+    cwd: Option<String>,
 
-        Self {
-            name: name.to_string(),
-            runtime: runtime,
-        }
-    }
-}
+    /// Add <EXEC> as a candidate path for the program to launch. Can be repeated. Replaces
+    /// the manifest "exec" when given.
+    #[arg(long, help_heading = "Manifest")]
+    exec: Vec<String>,
 
-impl Display for Target {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.name)
-    }
-}
+    /// Write output to <OUTPUT>. The "{target}" and "{exe_suffix}" placeholders expand to
+    /// the target triple and the executable file extension of the target (e.g. ".exe").
+    #[arg(long)]
+    output: String,
 
-/// Create a self-contained launcher for an arbitrary executable.
-#[derive(Debug, Args)]
-pub struct Command {
-    /// The manifest file.
+    /// Embed the program at <EMBED> into the launcher. The launcher runs it with the
+    /// manifest "argv" as arguments, so it needs no other file to work.
     #[arg(long)]
-    manifest: String,
+    embed: Option<String>,
+
+    /// Embed the directory at <PATH> into the launcher as a payload named <NAME>. The
+    /// runtime extracts it on demand and exposes its location via the
+    /// "{kickoff.payload.<NAME>}" substitution. Can be repeated.
+    #[arg(long = "embed-dir", value_name = "NAME=PATH", value_parser = parse_embed_dir)]
+    embed_dirs: Vec<(String, String)>,
 
-    /// Write output to <OUTPUT>.
+    /// The target platform triple, or "all" for every supported one. Can be repeated, in
+    /// which case <OUTPUT> must contain the "{target}" placeholder. Defaults to the host
+    /// platform.
+    #[arg(long = "target", value_name = "TARGET", value_parser = parse_target)]
+    targets: Vec<String>,
+
+    /// Use the runtime at <RUNTIME> for its target instead of the embedded one. Unless
+    /// "--target" is given, a launcher is only created for that target.
     #[arg(long)]
-    output: String,
+    runtime: Option<String>,
+
+    /// Look for runtimes in <RUNTIME_DIR>. They take precedence over the embedded ones.
+    /// Defaults to the value of the KICKOFF_RUNTIME_DIR environment variable.
+    #[arg(long = "runtime-dir")]
+    runtime_dir: Option<String>,
 
-    /// The target platform triple.
-    #[arg(long, required = false, value_parser = clap::value_parser!(Target), default_value_t)]
-    target: Target,
+    /// The program arguments. Replaces the manifest "argv" when given.
+    #[arg(last = true, value_name = "ARGV")]
+    argv: Vec<String>,
 }
 
 impl Command {
     pub fn execute(&self) -> ExitCode {
-        let manifest = match self.read_manifest() {
-            Ok(m) => m,
+        let mut registry = Registry::embedded();
+
+        if let Some(dir) = registry::runtime_dir(self.runtime_dir.as_deref()) {
+            if let Err(err) = registry.add_dir(&dir) {
+                eprintln!(
+                    "[ERROR] Failed to read runtime directory {}: {}",
+                    dir.display(),
+                    err
+                );
+                return ExitCode::from(1);
+            }
+        }
+
+        let runtime_target = match &self.runtime {
+            Some(path) => match registry.add_file(Path::new(path)) {
+                Ok(t) => Some(t),
+                Err(err) => {
+                    eprintln!("[ERROR] Failed to read runtime file: {}", err);
+                    return ExitCode::from(1);
+                }
+            },
+            None => None,
+        };
+
+        let targets = match self.targets(&registry, runtime_target) {
+            Ok(t) => t,
             Err(err) => {
-                eprintln!("[ERROR] Failed to read manifest file: {}", err);
+                eprintln!("[ERROR] {}", err);
                 return ExitCode::from(1);
             }
         };
 
-        match self.write_output(&manifest) {
-            Ok(_) => ExitCode::from(0),
+        if targets.len() > 1 && !self.output.contains(TARGET_PLACEHOLDER) {
+            eprintln!(
+                "[ERROR] The output must contain \"{}\" when creating launchers for more than one target",
+                TARGET_PLACEHOLDER
+            );
+            return ExitCode::from(1);
+        }
+
+        let document = match &self.manifest {
+            Some(path) => match manifest::Document::read(path, self.manifest_format) {
+                Ok(d) => Some(d),
+                Err(err) => {
+                    eprintln!("[ERROR] Failed to read manifest file: {}", err);
+                    return ExitCode::from(1);
+                }
+            },
+            None => None,
+        };
+
+        let payloads = match self.pack_payloads() {
+            Ok(p) => p,
             Err(err) => {
                 eprintln!("[ERROR] Failed to create launcher file: {}", err);
-                ExitCode::from(1)
+                return ExitCode::from(1);
+            }
+        };
+
+        // Launchers are independent of each other. Thus, they are all created in parallel.
+        let results = std::thread::scope(|scope| {
+            let handles = targets
+                .iter()
+                .map(|target| {
+                    scope.spawn(|| self.create(&registry, document.as_ref(), &payloads, target))
+                })
+                .collect::<Vec<_>>();
+
+            handles
+                .into_iter()
+                .map(|h| h.join().expect("failed to create launcher"))
+                .collect::<Vec<_>>()
+        });
+
+        let mut code = ExitCode::from(0);
+
+        for (target, result) in targets.iter().zip(results) {
+            if let Err(err) = result {
+                match targets.len() {
+                    1 => eprintln!("[ERROR] {}", err),
+                    _ => eprintln!("[ERROR] {}: {}", target, err),
+                }
+                code = ExitCode::from(1);
             }
         }
+
+        code
     }
 
-    fn read_manifest(&self) -> Result<Manifest, std::io::Error> {
-        let reader = File::open(&self.manifest)?;
-        let manifest = serde_json::from_reader(reader)?;
-        Ok(manifest)
+    /// Returns the targets to create launchers for. Unless given, it is the one of the
+    /// "--runtime" file or, failing that, the host one.
+    fn targets(
+        &self,
+        registry: &Registry,
+        runtime_target: Option<Target>,
+    ) -> Result<Vec<Target>, String> {
+        if self.targets.is_empty() {
+            return match runtime_target.or_else(Target::host) {
+                Some(target) => Ok(vec![target]),
+                None => Err(format!(
+                    "Unsupported host platform: {}",
+                    Target::host_platform()
+                )),
+            };
+        }
+
+        if self.targets.iter().any(|t| t == target::ALL) {
+            return Ok(registry.targets());
+        }
+
+        let mut targets = Vec::<Target>::new();
+
+        for name in &self.targets {
+            if !targets.iter().any(|t| &t.name == name) {
+                // Names were already validated by "parse_target".
+                targets.push(name.parse().expect("unrecognized target"));
+            }
+        }
+
+        Ok(targets)
     }
 
-    #[cfg(unix)]
-    fn write_output(&self, manifest: &Manifest) -> Result<(), std::io::Error> {
-        use std::os::unix::fs::OpenOptionsExt;
+    fn create(
+        &self,
+        registry: &Registry,
+        document: Option<&manifest::Document>,
+        payloads: &[(String, Vec<u8>)],
+        target: &Target,
+    ) -> Result<(), String> {
+        let runtime = registry.get(target).ok_or_else(|| {
+            format!(
+                "No runtime available for target: {} (see \"kickoff list-targets\")",
+                target
+            )
+        })?;
 
-        let mut writer = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .mode(0o755)
-            .open(&self.output)?;
+        let manifest = self
+            .read_manifest(document, target)
+            .map_err(|err| format!("Failed to read manifest file: {}", err))?;
 
-        writer.write_all(self.target.runtime)?;
-        kickoff::io::write_manifest(&mut writer, manifest)?;
+        self.validate_manifest(&manifest, target)
+            .map_err(|err| format!("Invalid manifest file: {}", err))?;
 
-        Ok(())
+        self.write_output(
+            &manifest,
+            payloads,
+            runtime,
+            Path::new(&self.output(target)),
+        )
+        .map_err(|err| format!("Failed to create launcher file: {}", err))
     }
 
-    #[cfg(windows)]
-    fn write_output(&self, manifest: &Manifest) -> Result<(), std::io::Error> {
-        let mut writer = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(&self.output)?;
+    /// Returns the output path for the given target, with the placeholders expanded.
+    fn output(&self, target: &Target) -> String {
+        self.output
+            .replace(TARGET_PLACEHOLDER, &target.name)
+            .replace(EXE_SUFFIX_PLACEHOLDER, target.exe_suffix())
+    }
+
+    fn read_manifest(
+        &self,
+        document: Option<&manifest::Document>,
+        target: &Target,
+    ) -> Result<Manifest, std::io::Error> {
+        let manifest = match document {
+            Some(document) => document.resolve(target)?,
+            None => Manifest::default(),
+        };
+
+        let mut builder = ManifestBuilder::from(manifest);
+
+        if !self.argv.is_empty() {
+            builder = builder.argv(&self.argv);
+        }
+
+        for (key, value) in &self.env {
+            builder = builder.env(key, value);
+        }
+
+        if !self.exec.is_empty() {
+            builder = builder.exec_candidates(&self.exec);
+        }
+
+        if let Some(cwd) = &self.cwd {
+            builder = builder.cwd(cwd);
+        }
+
+        Ok(builder.build())
+    }
+
+    fn validate_manifest(&self, manifest: &Manifest, target: &Target) -> Result<(), String> {
+        let unix_only = [
+            ("limits", manifest.limits.is_some()),
+            ("stdio", manifest.stdio.is_some()),
+            ("lock", manifest.lock.is_some()),
+            ("daemon", manifest.daemon.is_some()),
+        ];
+
+        let linux_only = [
+            ("sandbox", manifest.sandbox.is_some()),
+            ("security", manifest.security.is_some()),
+        ];
+
+        let unsupported = unix_only
+            .iter()
+            .filter(|_| target.is_windows())
+            .chain(linux_only.iter().filter(|_| !target.is_linux()))
+            .find(|(_, present)| *present);
+
+        if let Some((section, _)) = unsupported {
+            return Err(format!(
+                "the \"{}\" section is not supported by target: {}",
+                section, target
+            ));
+        }
+
+        if self.embed.is_some() && !manifest.exec.is_empty() {
+            return Err(String::from(
+                "the \"exec\" section cannot be used along with an embedded program",
+            ));
+        }
+
+        for (i, (name, _)) in self.embed_dirs.iter().enumerate() {
+            if self.embed_dirs[..i].iter().any(|(other, _)| other == name) {
+                return Err(format!(
+                    "the \"{}\" payload is embedded more than once",
+                    name
+                ));
+            }
+        }
+
+        if let Some(name) = manifest
+            .payload_references()
+            .into_iter()
+            .find(|name| !self.embed_dirs.iter().any(|(n, _)| n == name))
+        {
+            return Err(format!(
+                "the \"{}\" payload is referenced but not embedded",
+                name
+            ));
+        }
+
+        if let Some(limits) = &manifest.limits {
+            limits.umask_mode()?;
+        }
+
+        if let Some(exit) = &manifest.exit {
+            if let Some(code) = exit.codes.values().find(|c| !(0..=255).contains(*c)) {
+                return Err(format!(
+                    "the \"exit\" code {} is out of range (expected 0 to 255)",
+                    code
+                ));
+            }
+        }
+
+        if let Some(sandbox) = &manifest.sandbox {
+            if !sandbox.mounts.is_empty() && !sandbox.namespaces.contains(&Namespace::Mount) {
+                return Err(String::from(
+                    "the \"sandbox\" mounts require the \"mount\" namespace",
+                ));
+            }
+        }
+
+        if let Some(seccomp) = manifest.security.as_ref().and_then(|s| s.seccomp.as_ref()) {
+            let arch = Arch::from_triple(&target.name)
+                .ok_or(format!("seccomp is not supported by target: {}", target))?;
 
-        writer.write_all(self.target.runtime)?;
-        kickoff::io::write_manifest(&mut writer, manifest)?;
+            seccomp::compile(seccomp, arch)?;
+        }
 
         Ok(())
     }
+
+    fn write_output(
+        &self,
+        manifest: &Manifest,
+        payloads: &[(String, Vec<u8>)],
+        runtime: &Runtime,
+        output: &Path,
+    ) -> Result<(), std::io::Error> {
+        if let Some(parent) = output.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o755);
+        }
+
+        let mut writer = options.open(output)?;
+
+        writer.write_all(&runtime.content)?;
+
+        match &self.embed {
+            Some(path) => {
+                let mut program = File::open(path).map_err(|err| {
+                    std::io::Error::new(err.kind(), format!("open \"{}\": {}", path, err))
+                })?;
+
+                kickoff::io::write_launcher(&mut writer, &mut program, payloads, manifest)
+            }
+            None => {
+                kickoff::io::write_launcher(&mut writer, &mut std::io::empty(), payloads, manifest)
+            }
+        }
+    }
+
+    fn pack_payloads(&self) -> Result<Vec<(String, Vec<u8>)>, std::io::Error> {
+        self.embed_dirs
+            .iter()
+            .map(|(name, path)| {
+                let archive =
+                    kickoff::archive::pack(Path::new(path), Vec::new()).map_err(|err| {
+                        std::io::Error::new(err.kind(), format!("archive \"{}\": {}", path, err))
+                    })?;
+
+                Ok((name.clone(), archive))
+            })
+            .collect()
+    }
+}
+
+fn parse_target(value: &str) -> Result<String, String> {
+    match value == target::ALL {
+        true => Ok(value.to_string()),
+        false => value.parse::<Target>().map(|t| t.name),
+    }
+}
+
+pub fn parse_embed_dir(value: &str) -> Result<(String, String), String> {
+    let (name, path) = value
+        .split_once('=')
+        .ok_or_else(|| String::from("expected <NAME>=<PATH>"))?;
+
+    if !kickoff::is_payload_name(name) {
+        return Err(format!(
+            "invalid payload name (only letters, digits, '_' and '-' are allowed): {}",
+            name
+        ));
+    }
+
+    if !Path::new(path).is_dir() {
+        return Err(format!("not a directory: {}", path));
+    }
+
+    Ok((name.to_string(), path.to_string()))
+}
+
+fn parse_env(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        Some(("", _)) => Err(String::from("empty variable name")),
+        Some((key, value)) => Ok((key.to_string(), value.to_string())),
+        None => Err(String::from("expected <KEY>=<VALUE>")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+    use clap::Parser;
+
+    #[derive(Debug, Parser)]
+    struct Cli {
+        #[command(flatten)]
+        command: Command,
+    }
+
+    fn command(args: &[&str]) -> Command {
+        Cli::try_parse_from(["create"].iter().chain(args))
+            .unwrap()
+            .command
+    }
+
+    fn document(name: &str, content: &str) -> manifest::Document {
+        let dir = TempDir::new(&format!("create-{}", name));
+        let path = dir.join("manifest");
+        std::fs::write(&path, content).unwrap();
+
+        manifest::Document::read(path.to_str().unwrap(), None).unwrap()
+    }
+
+    fn linux() -> Target {
+        "x86_64-unknown-linux-gnu".parse().unwrap()
+    }
+
+    fn windows() -> Target {
+        "x86_64-pc-windows-gnu".parse().unwrap()
+    }
+
+    #[test]
+    fn output_when_placeholders_then_expands_them() {
+        let command = command(&["--output=dist/{target}/tool{exe_suffix}", "--", "app"]);
+
+        assert_eq!(
+            command.output(&linux()),
+            "dist/x86_64-unknown-linux-gnu/tool"
+        );
+        assert_eq!(
+            command.output(&windows()),
+            "dist/x86_64-pc-windows-gnu/tool.exe"
+        );
+    }
+
+    #[test]
+    fn output_when_no_placeholders_then_unchanged() {
+        let command = command(&["--output=tool", "--", "app"]);
+
+        assert_eq!(command.output(&windows()), "tool");
+    }
+
+    #[test]
+    fn targets_when_repeated_then_deduplicated_in_order() {
+        let command = command(&[
+            "--output={target}",
+            "--target=x86_64-unknown-linux-gnu",
+            "--target=x86_64-pc-windows-gnu",
+            "--target=x86_64-unknown-linux-gnu",
+            "--",
+            "app",
+        ]);
+
+        let targets = command.targets(&Registry::default(), None).unwrap();
+
+        assert_eq!(targets, vec![linux(), windows()]);
+    }
+
+    #[test]
+    fn targets_when_all_then_every_registry_target() {
+        let dir = TempDir::new("create-all");
+
+        for target in [windows(), linux()] {
+            let header = format!("k1ck0ff-runtime:{}:0.0.1\0", target);
+            std::fs::write(dir.join(&target.name), header).unwrap();
+        }
+
+        let mut registry = Registry::default();
+        registry.add_dir(dir.path()).unwrap();
+
+        let command = command(&[
+            "--output={target}",
+            "--target=x86_64-pc-windows-gnu",
+            "--target=all",
+            "--",
+            "app",
+        ]);
+
+        assert_eq!(
+            command.targets(&registry, None).unwrap(),
+            vec![windows(), linux()]
+        );
+    }
+
+    #[test]
+    fn targets_when_none_then_runtime_target() {
+        let command = command(&["--output=tool", "--", "app"]);
+
+        assert_eq!(
+            command
+                .targets(&Registry::default(), Some(windows()))
+                .unwrap(),
+            vec![windows()]
+        );
+    }
+
+    #[test]
+    fn read_manifest_when_exec_flags_then_replace_manifest_exec() {
+        let document = document(
+            "exec-replaced.json",
+            r#"{"argv": ["-v"], "env": {}, "exec": ["/usr/bin/python3", "/bin/python3"]}"#,
+        );
+        let command = command(&[
+            "--manifest=unused.json",
+            "--output=out",
+            "--exec=/opt/python/bin/python3",
+            "--exec=/usr/local/bin/python3",
+        ]);
+
+        let manifest = command.read_manifest(Some(&document), &linux()).unwrap();
+
+        assert_eq!(
+            manifest.exec,
+            vec!["/opt/python/bin/python3", "/usr/local/bin/python3"]
+        );
+        assert_eq!(manifest.argv, vec!["-v"]);
+    }
+
+    #[test]
+    fn read_manifest_when_no_exec_flags_then_keep_manifest_exec() {
+        let document = document(
+            "exec-kept.json",
+            r#"{"argv": [], "env": {}, "exec": ["/usr/bin/python3"]}"#,
+        );
+        let command = command(&["--manifest=unused.json", "--output=out", "--", "-v"]);
+
+        let manifest = command.read_manifest(Some(&document), &linux()).unwrap();
+
+        assert_eq!(manifest.exec, vec!["/usr/bin/python3"]);
+        assert_eq!(manifest.argv, vec!["-v"]);
+    }
+
+    #[test]
+    fn validate_manifest_when_exit_code_out_of_range_then_fails() {
+        let document = document(
+            "exit-out-of-range.json",
+            r#"{"argv": ["app"], "exit": {"codes": {"1": 256}}}"#,
+        );
+        let command = command(&["--manifest=unused.json", "--output=out"]);
+
+        let manifest = command.read_manifest(Some(&document), &linux()).unwrap();
+
+        assert_eq!(
+            command.validate_manifest(&manifest, &linux()),
+            Err(String::from(
+                "the \"exit\" code 256 is out of range (expected 0 to 255)"
+            ))
+        );
+    }
+
+    #[test]
+    fn validate_manifest_when_exit_code_in_range_then_succeeds() {
+        let document = document(
+            "exit-in-range.json",
+            r#"{"argv": ["app"], "exit": {"codes": {"1": 255, "2": 0}}}"#,
+        );
+        let command = command(&["--manifest=unused.json", "--output=out"]);
+
+        let manifest = command.read_manifest(Some(&document), &linux()).unwrap();
+
+        assert_eq!(command.validate_manifest(&manifest, &linux()), Ok(()));
+    }
 }
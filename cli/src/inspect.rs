@@ -0,0 +1,82 @@
+// Copyright (c) 2023 The Nimbus Authors. All rights reserved.
+//
+// The use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+use std::{fs::File, process::ExitCode};
+
+use clap::{ArgGroup, Args};
+
+use crate::{manifest, target::Target};
+
+/// Show the fully resolved manifest of a manifest file or an existing launcher.
+#[derive(Debug, Args)]
+#[command(group(
+    ArgGroup::new("source")
+        .required(true)
+        .args(["manifest", "launcher"]),
+))]
+pub struct Command {
+    /// The manifest file, or "-" to read it from the standard input.
+    #[arg(long)]
+    manifest: Option<String>,
+
+    /// The format of the manifest file. Guessed from its extension if not given.
+    #[arg(long = "manifest-format", value_enum, requires = "manifest")]
+    manifest_format: Option<manifest::Format>,
+
+    /// The launcher whose embedded manifest to show.
+    #[arg(long)]
+    launcher: Option<String>,
+
+    /// The target platform triple to resolve the manifest "platform" sections for. Defaults
+    /// to the host one.
+    #[arg(long, value_parser = clap::value_parser!(Target))]
+    target: Option<Target>,
+}
+
+impl Command {
+    pub fn execute(&self) -> ExitCode {
+        let target = match self.target.clone().or_else(Target::host) {
+            Some(t) => t,
+            None => {
+                eprintln!(
+                    "[ERROR] Unsupported host platform: {}",
+                    Target::host_platform()
+                );
+                return ExitCode::from(1);
+            }
+        };
+
+        let manifest = match (&self.manifest, &self.launcher) {
+            (Some(path), _) => manifest::read(path, self.manifest_format, &target),
+            (_, Some(path)) => {
+                File::open(path).and_then(|mut f| kickoff::io::read_manifest(&mut f))
+            }
+            (None, None) => unreachable!("clap requires either a manifest or a launcher"),
+        };
+
+        let manifest = match manifest {
+            Ok(m) => m,
+            Err(err) => {
+                eprintln!("[ERROR] Failed to read manifest: {}", err);
+                return ExitCode::from(1);
+            }
+        };
+
+        // Going through "serde_json::Value" sorts the keys of all maps (e.g. "env"), which
+        // makes the output stable and easy to diff.
+        let output = serde_json::to_value(&manifest).and_then(|v| serde_json::to_string_pretty(&v));
+
+        match output {
+            Ok(output) => {
+                println!("{}", output);
+                ExitCode::from(0)
+            }
+            Err(err) => {
+                eprintln!("[ERROR] Failed to print manifest: {}", err);
+                ExitCode::from(1)
+            }
+        }
+    }
+}
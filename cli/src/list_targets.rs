@@ -0,0 +1,226 @@
+// Copyright (c) 2023 The Nimbus Authors. All rights reserved.
+//
+// The use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+use std::process::ExitCode;
+
+use clap::Args;
+use serde_json::json;
+
+use kickoff::header::Header;
+
+use crate::{
+    registry::{self, Registry, Runtime},
+    target::Target,
+};
+
+/// List the targets launchers can be created for.
+#[derive(Debug, Args)]
+pub struct Command {
+    /// Look for runtimes in <RUNTIME_DIR> as well. Defaults to the value of the
+    /// KICKOFF_RUNTIME_DIR environment variable.
+    #[arg(long = "runtime-dir")]
+    runtime_dir: Option<String>,
+
+    /// Print the targets as JSON.
+    #[arg(long)]
+    json: bool,
+}
+
+struct Row<'a> {
+    target: Target,
+    runtime: &'a Runtime,
+    version: Option<String>,
+    note: Option<String>,
+}
+
+impl Command {
+    pub fn execute(&self) -> ExitCode {
+        let mut registry = Registry::embedded();
+
+        if let Some(dir) = registry::runtime_dir(self.runtime_dir.as_deref()) {
+            if let Err(err) = registry.add_dir(&dir) {
+                eprintln!(
+                    "[ERROR] Failed to read runtime directory {}: {}",
+                    dir.display(),
+                    err
+                );
+                return ExitCode::from(1);
+            }
+        }
+
+        let rows = registry
+            .targets()
+            .into_iter()
+            .filter_map(|target| {
+                let runtime = registry.get(&target)?;
+                let header = runtime.header();
+                let note = note(&target, header.as_ref());
+
+                Some(Row {
+                    version: header.map(|h| h.version),
+                    target,
+                    runtime,
+                    note,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let host = Target::host();
+
+        match self.json {
+            true => print_json(&rows, host.as_ref()),
+            false => print_text(&rows, host.as_ref()),
+        }
+
+        ExitCode::from(0)
+    }
+}
+
+/// Describes how the target runs when its runtime is the one of another target (e.g.
+/// Windows on aarch64 runs the x86_64 runtime via emulation).
+fn note(target: &Target, header: Option<&Header>) -> Option<String> {
+    header
+        .and_then(|h| h.triple.parse::<Target>().ok())
+        .filter(|t| t.name != target.name)
+        .map(|t| match t.arch() == target.arch() {
+            true => format!("uses the {} runtime", t),
+            false => format!("uses the {} runtime via emulation", t),
+        })
+}
+
+fn print_text(rows: &[Row], host: Option<&Target>) {
+    let version = |row: &Row| {
+        row.version
+            .clone()
+            .unwrap_or_else(|| String::from("unknown"))
+    };
+
+    let name_width = rows.iter().map(|r| r.target.name.len()).max().unwrap_or(0);
+    let version_width = rows.iter().map(|r| version(r).len()).max().unwrap_or(0);
+    let size_width = rows
+        .iter()
+        .map(|r| r.runtime.content.len().to_string().len())
+        .max()
+        .unwrap_or(0);
+
+    for row in rows {
+        let source = match &row.runtime.path {
+            Some(path) => path.display().to_string(),
+            None => String::from("embedded"),
+        };
+
+        let mut line = format!(
+            "{:name_width$}  version {:version_width$}  {:>size_width$} bytes  {}",
+            row.target.name,
+            version(row),
+            row.runtime.content.len(),
+            source,
+            name_width = name_width,
+            version_width = version_width,
+            size_width = size_width,
+        );
+
+        if host == Some(&row.target) {
+            line.push_str("  (host)");
+        }
+
+        if let Some(note) = &row.note {
+            line.push_str(&format!("  ({})", note));
+        }
+
+        println!("{}", line);
+    }
+
+    match host {
+        Some(t) => println!("Host platform {} maps to {}", Target::host_platform(), t),
+        None => println!("Host platform {} is not supported", Target::host_platform()),
+    }
+}
+
+fn print_json(rows: &[Row], host: Option<&Target>) {
+    let targets = rows
+        .iter()
+        .map(|row| {
+            json!({
+                "target": row.target.name,
+                "version": row.version,
+                "size": row.runtime.content.len(),
+                "path": row.runtime.path,
+                "host": host == Some(&row.target),
+                "note": row.note,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let output = json!({
+        "host": {
+            "platform": Target::host_platform(),
+            "target": host.map(|t| &t.name),
+        },
+        "targets": targets,
+    });
+
+    println!("{:#}", output);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target(name: &str) -> Target {
+        name.parse().unwrap()
+    }
+
+    fn header(triple: &str) -> Header {
+        Header {
+            triple: triple.to_string(),
+            version: String::from("0.0.1"),
+        }
+    }
+
+    #[test]
+    fn note_when_own_runtime_then_none() {
+        let linux = target("x86_64-unknown-linux-gnu");
+
+        assert_eq!(
+            note(&linux, Some(&header("x86_64-unknown-linux-gnu"))),
+            None
+        );
+        assert_eq!(note(&linux, None), None);
+    }
+
+    #[test]
+    fn note_when_runtime_of_other_arch_then_via_emulation() {
+        let note = note(
+            &target("aarch64-pc-windows-gnu"),
+            Some(&header("x86_64-pc-windows-gnu")),
+        );
+
+        assert_eq!(
+            note.as_deref(),
+            Some("uses the x86_64-pc-windows-gnu runtime via emulation")
+        );
+    }
+
+    #[test]
+    fn note_when_runtime_of_same_arch_then_no_emulation() {
+        let note = note(
+            &target("x86_64-pc-windows-gnu"),
+            Some(&header("x86_64-pc-windows-gnullvm")),
+        );
+
+        assert_eq!(
+            note.as_deref(),
+            Some("uses the x86_64-pc-windows-gnullvm runtime")
+        );
+    }
+
+    #[test]
+    fn note_when_header_triple_not_valid_then_none() {
+        let note = note(&target("x86_64-pc-windows-gnu"), Some(&header("windows")));
+
+        assert_eq!(note, None);
+    }
+}
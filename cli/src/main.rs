@@ -8,7 +8,16 @@ use std::process::ExitCode;
 use clap::{command, Parser, Subcommand};
 use phf::phf_map;
 
+mod cache;
 mod create;
+mod inspect;
+mod list_targets;
+mod manifest;
+mod registry;
+mod run;
+mod target;
+#[cfg(test)]
+mod testing;
 
 static HOST_PLATFORMS: phf::Map<&'static str, &'static str> = phf_map! {
     "aarch64-linux" => "aarch64-unknown-linux-gnu",
@@ -19,6 +28,7 @@ static HOST_PLATFORMS: phf::Map<&'static str, &'static str> = phf_map! {
     "x86_64-windows" => "x86_64-pc-windows-gnu",
 };
 
+#[cfg(feature = "bazel")]
 static RUNTIMES: phf::Map<&'static str, &'static [u8]> = phf_map! {
     "aarch64-apple-macos-none" => include_bytes!(concat!("../../",env!("BAZEL_GENDIR"), "/runtime/kickoff-runtime-aarch64-macos")),
     // For now, the "aarch64-pc-windows-gnu" target uses the "x86_64-pc-windows-gnu" runtime binary
@@ -31,6 +41,10 @@ static RUNTIMES: phf::Map<&'static str, &'static [u8]> = phf_map! {
     "x86_64-unknown-linux-gnu" => include_bytes!(concat!("../../", env!("BAZEL_GENDIR"), "/runtime/kickoff-runtime-x86_64-linux")),
 };
 
+// Only holds the runtime for the host platform, if any. See "build.rs".
+#[cfg(not(feature = "bazel"))]
+include!(concat!(env!("OUT_DIR"), "/runtimes.rs"));
+
 #[derive(Debug, Parser)]
 #[command(name = "kickoff", author, version, about, long_about = None )]
 struct Kickoff {
@@ -40,13 +54,21 @@ struct Kickoff {
 
 #[derive(Debug, Subcommand)]
 enum Commands {
-    Create(create::Command),
+    Cache(cache::Command),
+    Create(Box<create::Command>),
+    Inspect(inspect::Command),
+    ListTargets(list_targets::Command),
+    Run(run::Command),
 }
 
 fn main() -> ExitCode {
     let args = Kickoff::parse();
 
     match args.cmd {
+        Commands::Cache(cmd) => cmd.execute(),
         Commands::Create(cmd) => cmd.execute(),
+        Commands::Inspect(cmd) => cmd.execute(),
+        Commands::ListTargets(cmd) => cmd.execute(),
+        Commands::Run(cmd) => cmd.execute(),
     }
 }
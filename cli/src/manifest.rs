@@ -0,0 +1,461 @@
+// Copyright (c) 2023 The Nimbus Authors. All rights reserved.
+//
+// The use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+use std::{
+    fs::File,
+    io::{Error as IOError, ErrorKind as IOErrorKind, Read},
+    path::{Path, PathBuf},
+};
+
+use clap::ValueEnum;
+use serde_json::{Map, Value};
+
+use kickoff::Manifest;
+
+use crate::target::Target;
+
+/// The path that makes the manifest be read from the standard input.
+pub const STDIN: &str = "-";
+
+/// The key holding the path, or list of paths, of the manifests to inherit from. Relative
+/// paths are resolved against the directory of the manifest that names them.
+const EXTENDS: &str = "extends";
+
+/// The key holding the settings that only apply to some targets, keyed by a selector made
+/// of an operating system, a CPU architecture or both (e.g. "windows", "linux-aarch64").
+const PLATFORM: &str = "platform";
+
+const OPERATING_SYSTEMS: [&str; 3] = ["linux", "macos", "windows"];
+const ARCHITECTURES: [&str; 2] = ["aarch64", "x86_64"];
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Format {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl Format {
+    /// Guesses the format of a manifest file from its extension.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?;
+
+        match extension.to_ascii_lowercase().as_str() {
+            "json" => Some(Self::Json),
+            "toml" => Some(Self::Toml),
+            "yaml" | "yml" => Some(Self::Yaml),
+            _ => None,
+        }
+    }
+
+    pub fn parse(&self, content: &str) -> Result<Value, IOError> {
+        match self {
+            Self::Json => serde_json::from_str(content).map_err(IOError::from),
+            Self::Toml => toml::from_str(content).map_err(invalid_data),
+            Self::Yaml => serde_yaml::from_str(content).map_err(invalid_data),
+        }
+    }
+}
+
+/// A manifest source with all of its bases merged. It still holds the "platform" sections
+/// so it can be resolved for as many targets as needed while being read only once.
+#[derive(Debug)]
+pub struct Document {
+    value: Value,
+}
+
+impl Document {
+    /// Reads a manifest from the file at the given path, or from the standard input if
+    /// the path is "-". Unless a format is given, it is guessed from the file extension
+    /// and defaults to JSON.
+    ///
+    /// Manifests can inherit from others via "extends". The base manifests are merged in
+    /// the order they are listed and the manifest naming them is merged last. Thus, later
+    /// values take precedence over earlier ones. Tables (e.g. "env") are merged key by key
+    /// while any other value (e.g. "argv") is replaced as a whole.
+    pub fn read(path: &str, format: Option<Format>) -> Result<Self, IOError> {
+        let value = resolve(Path::new(path), format, &mut Vec::new())?;
+
+        Ok(Self { value })
+    }
+
+    /// Returns the manifest for the given target. The "platform" sections matching the
+    /// target are merged on top in order of specificity: architecture, operating system
+    /// and both.
+    pub fn resolve(&self, target: &Target) -> Result<Manifest, IOError> {
+        let mut value = self.value.clone();
+
+        resolve_platform(&mut value, target)?;
+
+        serde_json::from_value(value).map_err(IOError::from)
+    }
+}
+
+/// Reads the manifest at the given path and resolves it for the given target. See
+/// "Document" for details.
+pub fn read(path: &str, format: Option<Format>, target: &Target) -> Result<Manifest, IOError> {
+    Document::read(path, format)?.resolve(target)
+}
+
+fn resolve(
+    path: &Path,
+    format: Option<Format>,
+    chain: &mut Vec<PathBuf>,
+) -> Result<Value, IOError> {
+    let mut value = load(path, format)?;
+
+    let bases = match value.as_object_mut().and_then(|o| o.remove(EXTENDS)) {
+        None => Vec::new(),
+        Some(Value::String(base)) => vec![base],
+        Some(Value::Array(bases)) => bases
+            .into_iter()
+            .map(|base| match base {
+                Value::String(base) => Ok(base),
+                _ => Err(invalid_extends(path)),
+            })
+            .collect::<Result<_, _>>()?,
+        Some(_) => return Err(invalid_extends(path)),
+    };
+
+    if bases.is_empty() {
+        return Ok(value);
+    }
+
+    let (id, dir) = match path == Path::new(STDIN) {
+        true => (PathBuf::from(STDIN), PathBuf::new()),
+        false => {
+            let id = path.canonicalize()?;
+            let dir = id.parent().map(Path::to_path_buf).unwrap_or_default();
+            (id, dir)
+        }
+    };
+
+    if chain.contains(&id) {
+        return Err(IOError::new(
+            IOErrorKind::InvalidData,
+            format!("circular \"{}\" chain at: {}", EXTENDS, path.display()),
+        ));
+    }
+
+    chain.push(id);
+
+    let mut resolved = Value::Object(Map::new());
+    for base in bases {
+        merge(&mut resolved, resolve(&dir.join(base), None, chain)?);
+    }
+
+    chain.pop();
+
+    merge(&mut resolved, value);
+
+    Ok(resolved)
+}
+
+fn resolve_platform(value: &mut Value, target: &Target) -> Result<(), IOError> {
+    let sections = match value.as_object_mut().and_then(|o| o.remove(PLATFORM)) {
+        None => return Ok(()),
+        Some(Value::Object(sections)) => sections,
+        Some(_) => return Err(invalid_data(format!("\"{}\" must be a table", PLATFORM))),
+    };
+
+    let mut matching = Vec::new();
+
+    for (selector, section) in sections {
+        let (os, arch) = parse_selector(&selector)
+            .ok_or_else(|| invalid_data(format!("unrecognized platform: {}", selector)))?;
+
+        if !section.is_object() {
+            return Err(invalid_data(format!(
+                "the \"{}\" platform section must be a table",
+                selector
+            )));
+        }
+
+        if os.is_some_and(|os| os != target.os()) || arch.is_some_and(|a| a != target.arch()) {
+            continue;
+        }
+
+        let specificity = (os.is_some(), arch.is_some());
+        let rank = match specificity {
+            (false, true) => 0,
+            (true, false) => 1,
+            _ => 2,
+        };
+
+        matching.push((rank, section));
+    }
+
+    matching.sort_by_key(|(rank, _)| *rank);
+
+    for (_, section) in matching {
+        merge(value, section);
+    }
+
+    Ok(())
+}
+
+/// Splits a platform selector (e.g. "linux-aarch64") into its operating system and CPU
+/// architecture. Returns "None" if the selector is not valid.
+fn parse_selector(selector: &str) -> Option<(Option<&str>, Option<&str>)> {
+    // Architectures may contain underscores but never hyphens. Thus, splitting on hyphens
+    // is enough to tell the parts apart.
+    let mut os = None;
+    let mut arch = None;
+
+    for part in selector.split('-') {
+        if OPERATING_SYSTEMS.contains(&part) && os.is_none() {
+            os = Some(part);
+        } else if ARCHITECTURES.contains(&part) && arch.is_none() {
+            arch = Some(part);
+        } else {
+            return None;
+        }
+    }
+
+    Some((os, arch))
+}
+
+fn load(path: &Path, format: Option<Format>) -> Result<Value, IOError> {
+    let format = format
+        .or_else(|| Format::from_path(path))
+        .unwrap_or(Format::Json);
+
+    let mut content = String::new();
+
+    if path == Path::new(STDIN) {
+        std::io::stdin().read_to_string(&mut content)?;
+        return format.parse(&content);
+    }
+
+    File::open(path)
+        .and_then(|mut f| f.read_to_string(&mut content))
+        .and_then(|_| format.parse(&content))
+        .map_err(|err| IOError::new(err.kind(), format!("{}: {}", path.display(), err)))
+}
+
+fn merge(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                merge(base.entry(key).or_insert(Value::Null), value);
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+fn invalid_extends(path: &Path) -> IOError {
+    IOError::new(
+        IOErrorKind::InvalidData,
+        format!(
+            "\"{}\" must be a path or a list of paths in: {}",
+            EXTENDS,
+            path.display()
+        ),
+    )
+}
+
+fn invalid_data<E>(err: E) -> IOError
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    IOError::new(IOErrorKind::InvalidData, err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+    use serde_json::json;
+
+    fn read_value(path: &Path) -> Result<Value, IOError> {
+        Document::read(path.to_str().unwrap(), None).map(|d| d.value)
+    }
+
+    #[test]
+    fn read_when_extends_then_merges_bases_in_order() {
+        let dir = TempDir::new("manifest-order");
+        std::fs::create_dir_all(dir.join("common")).unwrap();
+
+        std::fs::write(
+            dir.join("common/first.toml"),
+            "argv = ['first']\ncwd = 'first'\n[env]\nA = 'first'\nB = 'first'\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("common/second.yaml"),
+            "argv: [second]\nenv: {B: second, C: second}\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("manifest.json"),
+            r#"{"extends": ["common/first.toml", "common/second.yaml"], "env": {"C": "manifest"}}"#,
+        )
+        .unwrap();
+
+        let value = read_value(&dir.join("manifest.json")).unwrap();
+
+        assert_eq!(
+            value,
+            json!({
+                "argv": ["second"],
+                "cwd": "first",
+                "env": {"A": "first", "B": "second", "C": "manifest"},
+            })
+        );
+    }
+
+    #[test]
+    fn read_when_extends_chain_then_resolves_paths_per_manifest() {
+        let dir = TempDir::new("manifest-chain");
+        std::fs::create_dir_all(dir.join("base")).unwrap();
+
+        std::fs::write(dir.join("base/root.json"), r#"{"env": {"ROOT": "1"}}"#).unwrap();
+        std::fs::write(
+            dir.join("base/middle.json"),
+            r#"{"extends": "root.json", "env": {"MIDDLE": "1"}}"#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("manifest.json"),
+            r#"{"extends": "base/middle.json", "argv": []}"#,
+        )
+        .unwrap();
+
+        let value = read_value(&dir.join("manifest.json")).unwrap();
+
+        assert_eq!(
+            value,
+            json!({"argv": [], "env": {"MIDDLE": "1", "ROOT": "1"}})
+        );
+    }
+
+    #[test]
+    fn merge_when_table_and_scalar_then_overlay_replaces_base() {
+        let mut value = json!({
+            "argv": ["a", "b"],
+            "env": {"A": "1"},
+            "stdio": "null",
+            "timeout": {"duration": 5, "grace": 1},
+        });
+
+        merge(
+            &mut value,
+            json!({
+                "argv": ["c"],
+                "env": {"B": "2"},
+                "stdio": {"close_fds": true},
+                "timeout": 10,
+            }),
+        );
+
+        assert_eq!(
+            value,
+            json!({
+                "argv": ["c"],
+                "env": {"A": "1", "B": "2"},
+                "stdio": {"close_fds": true},
+                "timeout": 10,
+            })
+        );
+    }
+
+    #[test]
+    fn read_when_extends_cycle_then_fails() {
+        let dir = TempDir::new("manifest-cycle");
+
+        std::fs::write(dir.join("a.json"), r#"{"extends": "b.json", "argv": []}"#).unwrap();
+        std::fs::write(dir.join("b.json"), r#"{"extends": "./a.json", "env": {}}"#).unwrap();
+
+        let err = read_value(&dir.join("a.json")).unwrap_err();
+
+        assert_eq!(err.kind(), IOErrorKind::InvalidData);
+        assert!(err.to_string().contains("circular"));
+    }
+
+    fn target(name: &str) -> Target {
+        name.parse().unwrap()
+    }
+
+    #[test]
+    fn parse_selector_when_valid_then_splits_parts() {
+        assert_eq!(parse_selector("linux"), Some((Some("linux"), None)));
+        assert_eq!(parse_selector("x86_64"), Some((None, Some("x86_64"))));
+        assert_eq!(
+            parse_selector("linux-aarch64"),
+            Some((Some("linux"), Some("aarch64")))
+        );
+        assert_eq!(
+            parse_selector("aarch64-macos"),
+            Some((Some("macos"), Some("aarch64")))
+        );
+    }
+
+    #[test]
+    fn parse_selector_when_not_valid_then_none() {
+        assert_eq!(parse_selector(""), None);
+        assert_eq!(parse_selector("freebsd"), None);
+        assert_eq!(parse_selector("linux-windows"), None);
+        assert_eq!(parse_selector("x86_64-aarch64"), None);
+        assert_eq!(parse_selector("linux-x86_64-gnu"), None);
+    }
+
+    #[test]
+    fn resolve_platform_when_matching_then_merges_by_specificity() {
+        let platform = json!({
+            "linux-aarch64": {"env": {"LEVEL": "both"}},
+            "linux": {"env": {"LEVEL": "os", "OS": "linux"}},
+            "aarch64": {"env": {"LEVEL": "arch", "ARCH": "aarch64"}},
+            "windows": {"argv": ["cmd.exe"]},
+            "x86_64": {"env": {"ARCH": "x86_64"}},
+        });
+
+        let mut value = json!({"argv": ["sh"], "env": {"LEVEL": "none"}, "platform": platform});
+        resolve_platform(&mut value, &target("aarch64-unknown-linux-gnu")).unwrap();
+
+        assert_eq!(
+            value,
+            json!({
+                "argv": ["sh"],
+                "env": {"LEVEL": "both", "OS": "linux", "ARCH": "aarch64"},
+            })
+        );
+
+        let mut value = json!({"argv": ["sh"], "env": {"LEVEL": "none"}, "platform": platform});
+        resolve_platform(&mut value, &target("x86_64-pc-windows-gnu")).unwrap();
+
+        assert_eq!(
+            value,
+            json!({"argv": ["cmd.exe"], "env": {"LEVEL": "none", "ARCH": "x86_64"}})
+        );
+    }
+
+    #[test]
+    fn resolve_platform_when_not_valid_then_fails() {
+        let linux = target("x86_64-unknown-linux-gnu");
+
+        for platform in [
+            json!(["linux"]),
+            json!({"freebsd": {}}),
+            json!({"linux": ["/bin/sh"]}),
+        ] {
+            let mut value = json!({"argv": [], "platform": platform});
+            let err = resolve_platform(&mut value, &linux).unwrap_err();
+
+            assert_eq!(err.kind(), IOErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn read_when_extends_not_valid_then_fails() {
+        let dir = TempDir::new("manifest-invalid");
+
+        std::fs::write(dir.join("manifest.json"), r#"{"extends": 42}"#).unwrap();
+
+        let err = read_value(&dir.join("manifest.json")).unwrap_err();
+
+        assert_eq!(err.kind(), IOErrorKind::InvalidData);
+    }
+}
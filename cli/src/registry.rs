@@ -0,0 +1,325 @@
+// Copyright (c) 2023 The Nimbus Authors. All rights reserved.
+//
+// The use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    io::{Error as IOError, ErrorKind as IOErrorKind},
+    path::{Path, PathBuf},
+};
+
+use kickoff::header::{self, Header};
+
+use crate::target::Target;
+
+/// The environment variable holding the default runtime search directory.
+pub const RUNTIME_DIR_ENV: &str = "KICKOFF_RUNTIME_DIR";
+
+#[derive(Debug)]
+pub struct Runtime {
+    pub content: Cow<'static, [u8]>,
+    /// The file the runtime was loaded from, if it was not embedded into the CLI.
+    pub path: Option<PathBuf>,
+}
+
+impl Runtime {
+    pub fn header(&self) -> Option<Header> {
+        header::find(&self.content)
+    }
+}
+
+/// Returns the runtime search directory: the given one or, if none, the one named by the
+/// KICKOFF_RUNTIME_DIR environment variable.
+pub fn runtime_dir(dir: Option<&str>) -> Option<PathBuf> {
+    dir.map(PathBuf::from)
+        .or_else(|| std::env::var_os(RUNTIME_DIR_ENV).map(PathBuf::from))
+}
+
+/// Fails unless the runtime was built from the same version as the CLI. Otherwise, it may
+/// not understand the launchers the CLI creates.
+fn check_version(header: &Header, path: &Path) -> Result<(), IOError> {
+    match header.version == env!("CARGO_PKG_VERSION") {
+        true => Ok(()),
+        false => Err(IOError::new(
+            IOErrorKind::InvalidData,
+            format!(
+                "runtime version {} does not match the CLI version {}: {}",
+                header.version,
+                env!("CARGO_PKG_VERSION"),
+                path.display()
+            ),
+        )),
+    }
+}
+
+/// The runtimes available to create launchers, keyed by target triple. Runtimes loaded
+/// from files take precedence over the ones embedded into the CLI.
+#[derive(Debug, Default)]
+pub struct Registry {
+    runtimes: BTreeMap<String, Runtime>,
+}
+
+impl Registry {
+    /// Returns a registry with the runtimes embedded into the CLI.
+    pub fn embedded() -> Self {
+        let runtimes = super::RUNTIMES
+            .entries()
+            .map(|(name, content)| {
+                let runtime = Runtime {
+                    content: Cow::Borrowed(*content),
+                    path: None,
+                };
+
+                (name.to_string(), runtime)
+            })
+            .collect();
+
+        Self { runtimes }
+    }
+
+    /// Adds every runtime found in the given directory. Files that are not runtimes (e.g.
+    /// launchers) are ignored, and so are runtimes of other versions (with a warning).
+    pub fn add_dir(&mut self, dir: &Path) -> Result<(), IOError> {
+        let mut paths = std::fs::read_dir(dir)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<Result<Vec<_>, IOError>>()?;
+
+        // Sorting makes the outcome deterministic should several files be runtimes for the
+        // same target.
+        paths.sort();
+
+        for path in paths.into_iter().filter(|p| p.is_file()) {
+            let content = std::fs::read(&path)?;
+
+            // Launchers embed a runtime too, but they are not runtimes themselves.
+            let header = match header::find(&content) {
+                Some(header) if !kickoff::io::is_launcher(&content) => header,
+                _ => continue,
+            };
+
+            match check_version(&header, &path) {
+                Ok(()) => self.insert(header, content, path),
+                Err(err) => eprintln!("[WARN] Ignoring runtime: {}", err),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Adds the runtime at the given path and returns its target. Fails if the runtime is
+    /// of another version.
+    pub fn add_file(&mut self, path: &Path) -> Result<Target, IOError> {
+        let content = std::fs::read(path)?;
+
+        if kickoff::io::is_launcher(&content) {
+            return Err(IOError::new(
+                IOErrorKind::InvalidData,
+                format!("a launcher, not a kickoff runtime: {}", path.display()),
+            ));
+        }
+
+        let header = header::find(&content).ok_or_else(|| {
+            IOError::new(
+                IOErrorKind::InvalidData,
+                format!("not a kickoff runtime: {}", path.display()),
+            )
+        })?;
+
+        check_version(&header, path)?;
+
+        let target = Target {
+            name: header.triple.clone(),
+        };
+
+        self.insert(header, content, path.to_path_buf());
+
+        Ok(target)
+    }
+
+    pub fn get(&self, target: &Target) -> Option<&Runtime> {
+        self.runtimes.get(&target.name)
+    }
+
+    /// Returns every target with a runtime, sorted by name.
+    pub fn targets(&self) -> Vec<Target> {
+        self.runtimes
+            .keys()
+            .map(|name| Target { name: name.clone() })
+            .collect()
+    }
+
+    fn insert(&mut self, header: Header, content: Vec<u8>, path: PathBuf) {
+        let runtime = Runtime {
+            content: Cow::Owned(content),
+            path: Some(path),
+        };
+
+        self.runtimes.insert(header.triple, runtime);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    fn write_runtime(path: &Path, triple: &str, version: &str) {
+        let content = format!("\x7fELF\0k1ck0ff-runtime:{}:{}\0code", triple, version);
+        std::fs::write(path, content).unwrap();
+    }
+
+    fn write_launcher(path: &Path, triple: &str) {
+        write_runtime(path, triple, env!("CARGO_PKG_VERSION"));
+
+        let mut file = std::fs::OpenOptions::new().append(true).open(path).unwrap();
+        kickoff::io::write_manifest(&mut file, &kickoff::Manifest::default()).unwrap();
+    }
+
+    /// A registry standing for "Registry::embedded", which depends on the build features.
+    fn embedded(triple: &str) -> Registry {
+        let runtime = Runtime {
+            content: Cow::Borrowed(b"embedded"),
+            path: None,
+        };
+
+        Registry {
+            runtimes: BTreeMap::from([(triple.to_string(), runtime)]),
+        }
+    }
+
+    #[test]
+    fn embedded_when_built_then_runtimes_have_cli_version() {
+        for (name, runtime) in Registry::embedded().runtimes {
+            let header = runtime.header().expect("embedded runtime without header");
+
+            assert_eq!(header.version, env!("CARGO_PKG_VERSION"), "{}", name);
+        }
+    }
+
+    #[test]
+    fn add_dir_when_same_target_as_embedded_then_takes_precedence() {
+        let dir = TempDir::new("registry-dir-precedence");
+        write_runtime(
+            &dir.join("linux"),
+            "x86_64-unknown-linux-gnu",
+            env!("CARGO_PKG_VERSION"),
+        );
+
+        let mut registry = embedded("x86_64-unknown-linux-gnu");
+        registry.add_dir(dir.path()).unwrap();
+
+        let linux = "x86_64-unknown-linux-gnu".parse().unwrap();
+        let runtime = registry.get(&linux).unwrap();
+
+        assert_eq!(runtime.path, Some(dir.join("linux")));
+        assert_eq!(registry.targets(), vec![linux]);
+    }
+
+    #[test]
+    fn add_dir_when_other_target_than_embedded_then_keeps_both() {
+        let dir = TempDir::new("registry-dir-both");
+        write_runtime(
+            &dir.join("windows"),
+            "x86_64-pc-windows-gnu",
+            env!("CARGO_PKG_VERSION"),
+        );
+
+        let mut registry = embedded("x86_64-unknown-linux-gnu");
+        registry.add_dir(dir.path()).unwrap();
+
+        let linux = "x86_64-unknown-linux-gnu".parse().unwrap();
+        let windows = "x86_64-pc-windows-gnu".parse().unwrap();
+
+        assert_eq!(registry.get(&linux).unwrap().path, None);
+        assert_eq!(
+            registry.get(&windows).unwrap().path,
+            Some(dir.join("windows"))
+        );
+        assert_eq!(registry.targets(), vec![windows, linux]);
+    }
+
+    #[test]
+    fn add_file_when_after_add_dir_then_takes_precedence() {
+        let dir = TempDir::new("registry-file-precedence");
+        std::fs::create_dir_all(dir.join("runtimes")).unwrap();
+        write_runtime(
+            &dir.join("runtimes/linux"),
+            "x86_64-unknown-linux-gnu",
+            env!("CARGO_PKG_VERSION"),
+        );
+        write_runtime(
+            &dir.join("patched"),
+            "x86_64-unknown-linux-gnu",
+            env!("CARGO_PKG_VERSION"),
+        );
+
+        let mut registry = embedded("x86_64-unknown-linux-gnu");
+        registry.add_dir(&dir.join("runtimes")).unwrap();
+        let target = registry.add_file(&dir.join("patched")).unwrap();
+
+        assert_eq!(
+            registry.get(&target).unwrap().path,
+            Some(dir.join("patched"))
+        );
+    }
+
+    #[test]
+    fn add_file_when_launcher_then_fails() {
+        let dir = TempDir::new("registry-file-launcher");
+        let path = dir.join("launcher");
+        write_launcher(&path, "x86_64-unknown-linux-gnu");
+
+        let mut registry = Registry::default();
+        let err = registry.add_file(&path).unwrap_err();
+
+        assert_eq!(err.kind(), IOErrorKind::InvalidData);
+        assert!(registry.targets().is_empty());
+    }
+
+    #[test]
+    fn add_dir_when_launcher_then_ignores_it() {
+        let dir = TempDir::new("registry-dir-launcher");
+        write_launcher(&dir.join("launcher"), "x86_64-unknown-linux-gnu");
+
+        let mut registry = Registry::default();
+        registry.add_dir(dir.path()).unwrap();
+
+        assert!(registry.targets().is_empty());
+    }
+
+    #[test]
+    fn add_file_when_other_version_then_fails() {
+        let dir = TempDir::new("registry-file-version");
+        let path = dir.join("runtime");
+        write_runtime(&path, "x86_64-unknown-linux-gnu", "99.0.0");
+
+        let mut registry = Registry::default();
+        let err = registry.add_file(&path).unwrap_err();
+
+        assert_eq!(err.kind(), IOErrorKind::InvalidData);
+        assert!(registry.targets().is_empty());
+    }
+
+    #[test]
+    fn add_dir_when_other_version_then_ignores_it() {
+        let dir = TempDir::new("registry-dir-version");
+        write_runtime(&dir.join("a"), "x86_64-unknown-linux-gnu", "99.0.0");
+        write_runtime(
+            &dir.join("b"),
+            "x86_64-pc-windows-gnu",
+            env!("CARGO_PKG_VERSION"),
+        );
+
+        let mut registry = Registry::default();
+        registry.add_dir(dir.path()).unwrap();
+
+        assert_eq!(
+            registry.targets(),
+            vec![Target {
+                name: String::from("x86_64-pc-windows-gnu")
+            }]
+        );
+    }
+}
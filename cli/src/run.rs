@@ -0,0 +1,146 @@
+// Copyright (c) 2023 The Nimbus Authors. All rights reserved.
+//
+// The use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use clap::Args;
+
+use kickoff::{
+    launch::{self, Context},
+    Manifest,
+};
+
+use crate::{create, manifest, target::Target};
+
+/// Run the program described by a manifest without creating a launcher.
+///
+/// The manifest directory stands for the launcher one (i.e. "{kickoff.self.dir}"). Only
+/// the settings affecting the command are applied (i.e. "argv", "env", "exec" and "cwd").
+#[derive(Debug, Args)]
+pub struct Command {
+    /// The manifest file, or "-" to read it from the standard input.
+    #[arg(long)]
+    manifest: String,
+
+    /// The format of the manifest file. Guessed from its extension if not given.
+    #[arg(long = "manifest-format", value_enum)]
+    manifest_format: Option<manifest::Format>,
+
+    /// Use the directory at <PATH> as the payload named <NAME>, as if it had been
+    /// embedded and extracted. Can be repeated.
+    #[arg(long = "embed-dir", value_name = "NAME=PATH", value_parser = create::parse_embed_dir)]
+    embed_dirs: Vec<(String, String)>,
+
+    /// The arguments appended to the manifest ones, like those given to a launcher.
+    #[arg(last = true, value_name = "ARGS")]
+    args: Vec<OsString>,
+}
+
+impl Command {
+    pub fn execute(&self) -> ExitCode {
+        let host = match Target::host() {
+            Some(t) => t,
+            None => {
+                eprintln!(
+                    "[ERROR] Unsupported host platform: {}",
+                    Target::host_platform()
+                );
+                return ExitCode::from(1);
+            }
+        };
+
+        let manifest = match manifest::read(&self.manifest, self.manifest_format, &host) {
+            Ok(m) => m,
+            Err(err) => {
+                eprintln!("[ERROR] Failed to read manifest: {}", err);
+                return ExitCode::from(1);
+            }
+        };
+
+        for section in ignored_sections(&manifest) {
+            eprintln!("[WARN] Ignoring the \"{}\" manifest section", section);
+        }
+
+        let context = match self.context() {
+            Ok(c) => c,
+            Err(err) => {
+                eprintln!("[ERROR] Failed to locate manifest: {}", err);
+                return ExitCode::from(1);
+            }
+        };
+
+        let plan = match launch::plan(&manifest, &context) {
+            Ok(p) => p,
+            Err(err) => {
+                eprintln!("[ERROR] Failed to resolve the command: {}", err);
+                return ExitCode::from(1);
+            }
+        };
+
+        let error = launch::execute(&plan).unwrap_err();
+
+        match plan.argv.first() {
+            Some(program) => eprintln!(
+                "[ERROR] Failed to run \"{}\": {}",
+                program.to_str().unwrap_or("<unprintable>"),
+                error
+            ),
+            None => eprintln!("[ERROR] Failed to run manifest: {}", error),
+        }
+
+        ExitCode::from(1)
+    }
+
+    fn context(&self) -> Result<Context, std::io::Error> {
+        // A manifest read from the standard input has no location of its own. Thus, the
+        // current directory stands for it.
+        let exe = match self.manifest.as_str() {
+            manifest::STDIN => std::env::current_dir()?,
+            path => Path::new(path).canonicalize()?,
+        };
+
+        let dir = match self.manifest.as_str() {
+            manifest::STDIN => exe.clone(),
+            _ => exe.parent().map(Path::to_path_buf).unwrap_or_default(),
+        };
+
+        let payloads = self
+            .embed_dirs
+            .iter()
+            .map(|(name, path)| Ok((name.clone(), PathBuf::from(path).canonicalize()?)))
+            .collect::<Result<_, std::io::Error>>()?;
+
+        Ok(Context {
+            exe,
+            dir,
+            payloads,
+            args: self.args.clone(),
+            env: std::env::vars_os().collect(),
+        })
+    }
+}
+
+/// Returns the name of the manifest sections that only the runtime implements.
+fn ignored_sections(manifest: &Manifest) -> Vec<&'static str> {
+    [
+        ("pre", !manifest.pre.is_empty()),
+        ("daemon", manifest.daemon.is_some()),
+        ("limits", manifest.limits.is_some()),
+        ("stdio", manifest.stdio.is_some()),
+        ("lock", manifest.lock.is_some()),
+        ("sandbox", manifest.sandbox.is_some()),
+        ("security", manifest.security.is_some()),
+        ("exit", manifest.exit.is_some()),
+        ("restart", manifest.restart.is_some()),
+        ("timeout", manifest.timeout.is_some()),
+    ]
+    .into_iter()
+    .filter_map(|(name, present)| present.then_some(name))
+    .collect()
+}
@@ -0,0 +1,84 @@
+// Copyright (c) 2023 The Nimbus Authors. All rights reserved.
+//
+// The use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+use std::{fmt::Display, str::FromStr};
+
+/// The value of "--target" that selects every supported target.
+pub const ALL: &str = "all";
+
+/// A target platform triple. Whether launchers can be created for it depends on the
+/// runtimes available (see "Registry").
+#[derive(Debug, Clone, PartialEq)]
+pub struct Target {
+    pub name: String,
+}
+
+impl FromStr for Target {
+    type Err = String;
+
+    fn from_str(target: &str) -> Result<Self, Self::Err> {
+        let parts = target.split('-').collect::<Vec<_>>();
+
+        let valid = parts.len() >= 3
+            && parts
+                .iter()
+                .all(|p| !p.is_empty() && p.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'));
+
+        match valid {
+            true => Ok(Self {
+                name: target.to_string(),
+            }),
+            false => Err(format!("unrecognized target: {}", target)),
+        }
+    }
+}
+
+impl Target {
+    /// The name of the platform of the current machine (e.g. "x86_64-linux").
+    pub fn host_platform() -> String {
+        format!("{}-{}", std::env::consts::ARCH, std::env::consts::OS)
+    }
+
+    /// The target of the current machine, if it is a supported one.
+    pub fn host() -> Option<Self> {
+        super::HOST_PLATFORMS
+            .get(Self::host_platform().as_str())
+            .map(|name| Self {
+                name: name.to_string(),
+            })
+    }
+
+    /// The file extension of the executables of the target, including the leading dot.
+    pub fn exe_suffix(&self) -> &'static str {
+        match self.is_windows() {
+            true => ".exe",
+            false => "",
+        }
+    }
+
+    pub fn is_windows(&self) -> bool {
+        self.name.contains("-windows-")
+    }
+
+    pub fn is_linux(&self) -> bool {
+        self.name.contains("-linux-")
+    }
+
+    /// The operating system of the target (e.g. "linux").
+    pub fn os(&self) -> &str {
+        self.name.split('-').nth(2).unwrap_or_default()
+    }
+
+    /// The CPU architecture of the target (e.g. "x86_64").
+    pub fn arch(&self) -> &str {
+        self.name.split('-').next().unwrap_or_default()
+    }
+}
+
+impl Display for Target {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
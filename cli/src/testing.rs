@@ -0,0 +1,41 @@
+// Copyright (c) 2023 The Nimbus Authors. All rights reserved.
+//
+// The use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+// Helpers shared by the unit tests of the CLI.
+
+use std::path::{Path, PathBuf};
+
+/// A directory for the files of a test. It is created empty and removed along with its
+/// content when dropped. Thus, it is also cleaned up when the test fails.
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    /// The name must be unique among the tests of the crate.
+    pub fn new(name: &str) -> Self {
+        let path =
+            std::env::temp_dir().join(format!("kickoff-cli-{}-{}", std::process::id(), name));
+
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+
+        Self { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn join<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        self.path.join(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
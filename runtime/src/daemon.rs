@@ -0,0 +1,229 @@
+// Copyright (c) 2023 The Nimbus Authors. All rights reserved.
+//
+// The use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+use std::{
+    error::Error,
+    ffi::{OsStr, OsString},
+    fs::{File, OpenOptions},
+    io::{Error as IOError, Read, Seek, SeekFrom, Write},
+    mem::ManuallyDrop,
+    os::unix::{
+        fs::MetadataExt,
+        io::{AsRawFd, FromRawFd, IntoRawFd},
+    },
+};
+
+use kickoff::Daemon;
+
+use kickoff::substitutions;
+
+/// The runtime once detached. Until it is ready, its stderr goes to the process that
+/// started it, which exits with code 1 if anything is reported.
+pub struct Detached {
+    report: File,
+}
+
+impl Detached {
+    /// Stops reporting to the process that started the runtime, which then exits with
+    /// code 0. Unless the manifest "stdio" section redirected it already, stderr goes to
+    /// the null device from now on.
+    pub fn ready(self) -> Result<(), IOError> {
+        // SAFETY: The standard streams were reopened on the null device by "start".
+        let stderr = ManuallyDrop::new(unsafe { File::from_raw_fd(2) });
+        let (stderr, report) = (stderr.metadata()?, self.report.metadata()?);
+
+        if (stderr.dev(), stderr.ino()) == (report.dev(), report.ino()) {
+            let null = OpenOptions::new().write(true).open("/dev/null")?;
+            kickoff::process::redirect_fd(null.as_raw_fd(), 2)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Detaches the runtime as described by the manifest "daemon" section. It only returns in
+/// the detached process, which has its stdin and stdout redirected to the null device and
+/// its PID written to the pidfile (if any). The pidfile path is subject to the same
+/// substitutions as the manifest "argv" and "env" sections.
+///
+/// The pidfile is locked for as long as the daemon runs. Its descriptor is inherited
+/// across "execve" so that the lock is held by the program, and released when it exits.
+pub fn start<T>(daemon: &Daemon, subs: &[T]) -> Result<Detached, Box<dyn Error>>
+where
+    T: Fn(&str) -> String,
+{
+    // IMPORTANT: The pidfile must be locked before detaching so that a running daemon is
+    // reported to the process that started the runtime. The lock is kept by the detached
+    // process since it shares the open file description.
+    let pidfile = match &daemon.pidfile {
+        Some(path) => {
+            let path = substitutions::apply(OsStr::new(path), subs);
+            Some(lock_pidfile(&path, daemon.refuse_if_running)?)
+        }
+        None => None,
+    };
+
+    // Closed standard streams would be reused by the files opened below. Thus, they are
+    // reopened on the null device first.
+    let null = loop {
+        let null = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/null")?;
+        if null.as_raw_fd() > 2 {
+            break null;
+        }
+        // Keeps the stream open past "execve".
+        let fd = null.into_raw_fd();
+        kickoff::process::redirect_fd(fd, fd)?;
+    };
+
+    let report = kickoff::process::daemonize().map_err(|err| format!("fork: {}", err))?;
+
+    for fd in 0..=1 {
+        kickoff::process::redirect_fd(null.as_raw_fd(), fd)?;
+    }
+    kickoff::process::redirect_fd(report.as_raw_fd(), 2)?;
+
+    if let Some((path, mut file)) = pidfile {
+        file.set_len(0)
+            .and_then(|_| file.seek(SeekFrom::Start(0)))
+            .and_then(|_| write!(file, "{}", std::process::id()))
+            .and_then(|_| kickoff::process::clear_cloexec(file.as_raw_fd()))
+            .map_err(|err| {
+                format!(
+                    "write \"{}\": {}",
+                    path.to_str().unwrap_or("<unprintable>"),
+                    err
+                )
+            })?;
+
+        // The descriptor must stay open for the lock to be held. See above.
+        let _ = file.into_raw_fd();
+    }
+
+    Ok(Detached { report })
+}
+
+/// Opens and locks the pidfile. If another daemon holds the lock already, it fails when
+/// "refuse_if_running" is set. Otherwise, the pidfile is returned unlocked and records the
+/// most recent daemon.
+fn lock_pidfile(path: &OsString, refuse_if_running: bool) -> Result<(OsString, File), String> {
+    let printable = path.to_str().unwrap_or("<unprintable>");
+
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+        .map_err(|err| format!("open \"{}\": {}", printable, err))?;
+
+    let locked = kickoff::process::try_lock(file.as_raw_fd())
+        .map_err(|err| format!("lock \"{}\": {}", printable, err))?;
+
+    if !locked && refuse_if_running {
+        let mut pid = String::new();
+        let holder = match file.read_to_string(&mut pid) {
+            Ok(_) if !pid.trim().is_empty() => format!("PID {}", pid.trim()),
+            _ => String::from("another process"),
+        };
+
+        return Err(format!(
+            "already running as {} (see \"{}\")",
+            holder, printable
+        ));
+    }
+
+    Ok((path.clone(), file))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, no_subs, TempDir};
+    use std::process::Output;
+
+    /// Runs "script" in a runtime detached by "start", optionally marked as ready first.
+    fn run_detached(ready: bool, script: &str) -> Output {
+        testing::run_in_child(script, move || {
+            let detached = start(&Daemon::default(), &no_subs())?;
+
+            // Otherwise, the script stderr still goes to the parent.
+            if ready {
+                detached.ready()?;
+            }
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn start_when_error_before_ready_then_reported_to_parent() {
+        let output = run_detached(false, "echo failure >&2; exit 1");
+
+        assert_eq!(output.status.code(), Some(1));
+        assert_eq!(output.stderr, b"failure\n");
+    }
+
+    #[test]
+    fn start_when_ready_then_parent_succeeds() {
+        let output = run_detached(true, "echo ignored >&2");
+
+        assert!(output.status.success());
+        assert!(output.stderr.is_empty());
+    }
+
+    fn pidfile(dir: &TempDir) -> OsString {
+        dir.join("pidfile").into_os_string()
+    }
+
+    #[test]
+    fn lock_pidfile_when_locked_and_refuse_if_running_then_fails() {
+        let dir = TempDir::new("daemon-locked");
+        let path = pidfile(&dir);
+
+        let (_, mut held) = lock_pidfile(&path, true).unwrap();
+        write!(held, "1234").unwrap();
+
+        assert_eq!(
+            lock_pidfile(&path, true).unwrap_err(),
+            format!(
+                "already running as PID 1234 (see \"{}\")",
+                path.to_str().unwrap()
+            )
+        );
+        assert!(lock_pidfile(&path, false).is_ok());
+    }
+
+    #[test]
+    fn lock_pidfile_when_stale_then_succeeds() {
+        let dir = TempDir::new("daemon-stale");
+        let path = pidfile(&dir);
+        std::fs::write(&path, std::process::id().to_string()).unwrap();
+
+        assert!(lock_pidfile(&path, true).is_ok());
+    }
+
+    #[test]
+    fn start_when_pidfile_then_program_holds_lock() {
+        let dir = TempDir::new("daemon-held");
+        let path = pidfile(&dir);
+        let daemon = Daemon {
+            pidfile: Some(path.to_str().unwrap().to_string()),
+            refuse_if_running: true,
+        };
+
+        let output =
+            testing::run_in_child("sleep 2", move || Ok(start(&daemon, &no_subs())?.ready()?));
+
+        assert!(output.status.success());
+
+        let pid = std::fs::read_to_string(&path).unwrap();
+        let err = lock_pidfile(&path, true).unwrap_err();
+
+        assert!(err.contains(&format!("PID {}", pid)), "{}", err);
+    }
+}
@@ -0,0 +1,223 @@
+// Copyright (c) 2023 The Nimbus Authors. All rights reserved.
+//
+// The use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    error::Error,
+    ffi::OsString,
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+use kickoff::cache::Cache;
+
+#[cfg(target_os = "linux")]
+use std::os::unix::io::AsRawFd;
+
+/// A copy of the program embedded in the launcher that is ready to be executed.
+pub enum Program {
+    /// A sealed in-memory file. It only exists for as long as the runtime keeps it open.
+    #[cfg(target_os = "linux")]
+    Memory(File),
+
+    /// A file extracted to the cache directory.
+    Cached(PathBuf),
+}
+
+impl Program {
+    /// Returns a path that can be used to execute the program.
+    pub fn path(&self) -> OsString {
+        match self {
+            #[cfg(target_os = "linux")]
+            Program::Memory(file) => OsString::from(format!("/proc/self/fd/{}", file.as_raw_fd())),
+            Program::Cached(path) => path.clone().into_os_string(),
+        }
+    }
+}
+
+/// Makes the program embedded in the launcher, if any, available for execution. On Linux,
+/// it is copied into an in-memory file so that nothing is written to disk. Otherwise, or
+/// if in-memory files are not available, it is extracted to the cache directory.
+pub fn load(launcher: &Path) -> Result<Option<Program>, Box<dyn Error>> {
+    let trailer = kickoff::io::read_trailer(&mut File::open(launcher)?)?;
+
+    if !trailer.has_program() {
+        return Ok(None);
+    }
+
+    #[cfg(target_os = "linux")]
+    if let Ok(file) = load_in_memory(launcher) {
+        return Ok(Some(Program::Memory(file)));
+    }
+
+    Ok(Some(Program::Cached(extract(launcher)?)))
+}
+
+/// Extracts the program embedded in the launcher to the per-user cache. Launchers that
+/// embed the same program share the extracted file.
+pub fn extract(launcher: &Path) -> Result<PathBuf, Box<dyn Error>> {
+    match Cache::user() {
+        Some(cache) => extract_into(launcher, &cache),
+        None => Err("cannot determine the cache directory".into()),
+    }
+}
+
+/// Extracts the given payloads embedded in the launcher to the per-user cache. Returns
+/// the directory each of them has been extracted to.
+pub fn extract_payloads(
+    launcher: &Path,
+    names: &BTreeSet<String>,
+) -> Result<HashMap<String, PathBuf>, Box<dyn Error>> {
+    if names.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let cache = Cache::user().ok_or("cannot determine the cache directory")?;
+
+    names
+        .iter()
+        .map(|name| Ok((name.clone(), extract_payload(launcher, name, &cache)?)))
+        .collect()
+}
+
+fn extract_payload(launcher: &Path, name: &str, cache: &Cache) -> Result<PathBuf, Box<dyn Error>> {
+    let mut archive = Vec::new();
+
+    if !kickoff::io::read_payload(&mut File::open(launcher)?, name, &mut archive)? {
+        return Err(format!("the \"{}\" payload is not embedded in the launcher", name).into());
+    }
+
+    let path = cache.store_archive(&archive).map_err(|err| {
+        format!(
+            "extract \"{}\" to \"{}\": {}",
+            name,
+            cache.root().display(),
+            err
+        )
+    })?;
+
+    Ok(path)
+}
+
+#[cfg(target_os = "linux")]
+fn load_in_memory(launcher: &Path) -> Result<File, Box<dyn Error>> {
+    let mut file = kickoff::process::memfd_create("kickoff")
+        .map_err(|err| format!("memfd_create: {}", err))?;
+
+    kickoff::io::read_program(&mut File::open(launcher)?, &mut file)?;
+    kickoff::process::seal(file.as_raw_fd()).map_err(|err| format!("seal: {}", err))?;
+
+    Ok(file)
+}
+
+fn extract_into(launcher: &Path, cache: &Cache) -> Result<PathBuf, Box<dyn Error>> {
+    let mut program = Vec::new();
+    kickoff::io::read_program(&mut File::open(launcher)?, &mut program)?;
+
+    let path = cache
+        .store_program(&program)
+        .map_err(|err| format!("extract to \"{}\": {}", cache.root().display(), err))?;
+
+    Ok(path)
+}
+
+#[cfg(test)]
+#[cfg(unix)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+    use kickoff::Manifest;
+    use std::{io::Write, process::Command};
+
+    fn launcher(dir: &TempDir, program: Option<&str>) -> PathBuf {
+        let path = dir.join("launcher");
+
+        let mut file = File::create(&path).unwrap();
+        file.write_all(b"runtime").unwrap();
+
+        match program {
+            Some(p) => kickoff::io::write_manifest_with_program(
+                &mut file,
+                &mut File::open(p).unwrap(),
+                &Manifest::default(),
+            ),
+            None => kickoff::io::write_manifest(&mut file, &Manifest::default()),
+        }
+        .unwrap();
+
+        path
+    }
+
+    fn run(path: OsString) -> i32 {
+        Command::new(path)
+            .args(["-c", "exit 7"])
+            .status()
+            .unwrap()
+            .code()
+            .unwrap()
+    }
+
+    #[test]
+    fn load_when_program_embedded_then_runs() {
+        let dir = TempDir::new("embedded-load");
+        let launcher = launcher(&dir, Some("/bin/sh"));
+
+        let program = load(&launcher).unwrap().unwrap();
+        assert_eq!(run(program.path()), 7);
+    }
+
+    #[test]
+    fn load_when_no_program_embedded_then_none() {
+        let dir = TempDir::new("embedded-none");
+        let launcher = launcher(&dir, None);
+
+        assert!(load(&launcher).unwrap().is_none());
+    }
+
+    #[test]
+    fn extract_payload_when_embedded_then_extracts_tree() {
+        let base = TempDir::new("embedded-payload");
+        let src = base.join("src");
+        let cache = Cache::new(base.join("cache"));
+
+        std::fs::create_dir_all(src.join("bin")).unwrap();
+        std::fs::write(src.join("bin/main.py"), "print('hi')").unwrap();
+
+        let archive = kickoff::archive::pack(&src, Vec::new()).unwrap();
+
+        let launcher = base.join("launcher");
+        let mut file = File::create(&launcher).unwrap();
+        file.write_all(b"runtime").unwrap();
+        kickoff::io::write_launcher(
+            &mut file,
+            &mut std::io::empty(),
+            &[(String::from("app"), archive)],
+            &Manifest::default(),
+        )
+        .unwrap();
+
+        let path = extract_payload(&launcher, "app", &cache).unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(path.join("bin/main.py")).unwrap(),
+            "print('hi')"
+        );
+        assert!(extract_payload(&launcher, "other", &cache).is_err());
+    }
+
+    #[test]
+    fn extract_when_called_twice_then_reuses_file() {
+        let dir = TempDir::new("embedded-extract");
+        let launcher = launcher(&dir, Some("/bin/sh"));
+        let cache = Cache::new(dir.join("cache"));
+
+        let first = extract_into(&launcher, &cache).unwrap();
+        let second = extract_into(&launcher, &cache).unwrap();
+
+        assert_eq!(first, second);
+        assert_eq!(cache.entries().unwrap().len(), 1);
+        assert_eq!(run(first.into_os_string()), 7);
+    }
+}
@@ -0,0 +1,75 @@
+// Copyright (c) 2023 The Nimbus Authors. All rights reserved.
+//
+// The use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+use kickoff::Exit;
+
+/// Translates the exit code of the launched program according to the manifest "exit"
+/// section. Returns the code the launcher should exit with and, if any, the message
+/// that should be printed to stderr. Messages are looked up by the original code.
+pub fn remap(exit: &Exit, code: i32) -> (i32, Option<&str>) {
+    let remapped = exit.codes.get(&code).copied().unwrap_or(code);
+    let message = exit.messages.get(&code).map(|m| m.as_str());
+
+    (remapped, message)
+}
+
+/// Returns the code the launcher should exit with for the given one. Codes outside of
+/// 0..=255 (e.g. negative ones or Windows NTSTATUS values) saturate to 255. Truncating
+/// them instead could turn a failure into a success (e.g. 256 into 0).
+pub fn to_exit_code(code: i32) -> u8 {
+    u8::try_from(code).unwrap_or(u8::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn remap_when_code_not_listed_then_noop() {
+        let exit = Exit {
+            codes: HashMap::from([(1, 2)]),
+            messages: HashMap::from([(1, String::from("some message"))]),
+        };
+
+        assert_eq!(remap(&exit, 0), (0, None));
+        assert_eq!(remap(&exit, 3), (3, None));
+    }
+
+    #[test]
+    fn remap_when_code_listed_then_remaps() {
+        let exit = Exit {
+            codes: HashMap::from([(137, 1), (3, 0)]),
+            messages: HashMap::from([(137, String::from("killed: out of memory?"))]),
+        };
+
+        assert_eq!(remap(&exit, 137), (1, Some("killed: out of memory?")));
+        assert_eq!(remap(&exit, 3), (0, None));
+    }
+
+    #[test]
+    fn remap_when_only_message_listed_then_keeps_code() {
+        let exit = Exit {
+            codes: HashMap::new(),
+            messages: HashMap::from([(2, String::from("usage error"))]),
+        };
+
+        assert_eq!(remap(&exit, 2), (2, Some("usage error")));
+    }
+
+    #[test]
+    fn to_exit_code_when_in_range_then_unchanged() {
+        assert_eq!(to_exit_code(0), 0);
+        assert_eq!(to_exit_code(1), 1);
+        assert_eq!(to_exit_code(255), 255);
+    }
+
+    #[test]
+    fn to_exit_code_when_out_of_range_then_saturates() {
+        assert_eq!(to_exit_code(256), 255);
+        assert_eq!(to_exit_code(-1), 255);
+        assert_eq!(to_exit_code(i32::MIN), 255);
+    }
+}
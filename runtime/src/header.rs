@@ -0,0 +1,27 @@
+// Copyright (c) 2023 The Nimbus Authors. All rights reserved.
+//
+// The use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+// Identifies the runtime so the CLI can tell its target triple and version without
+// running it. A runtime built for a platform missing below cannot be identified and,
+// thus, cannot be used via "kickoff create --runtime".
+#[cfg(all(target_arch = "aarch64", target_os = "linux"))]
+#[used]
+static HEADER: &str = kickoff::runtime_header!("aarch64-unknown-linux-gnu");
+
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+#[used]
+static HEADER: &str = kickoff::runtime_header!("x86_64-unknown-linux-gnu");
+
+#[cfg(all(target_arch = "aarch64", target_os = "macos"))]
+#[used]
+static HEADER: &str = kickoff::runtime_header!("aarch64-apple-macos-none");
+
+#[cfg(all(target_arch = "x86_64", target_os = "macos"))]
+#[used]
+static HEADER: &str = kickoff::runtime_header!("x86_64-apple-macos-none");
+
+#[cfg(all(target_arch = "x86_64", target_os = "windows"))]
+#[used]
+static HEADER: &str = kickoff::runtime_header!("x86_64-pc-windows-gnu");
@@ -0,0 +1,165 @@
+// Copyright (c) 2023 The Nimbus Authors. All rights reserved.
+//
+// The use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+use std::{
+    collections::HashMap,
+    error::Error,
+    ffi::{OsStr, OsString},
+    path::Path,
+};
+
+use kickoff::Action;
+
+use kickoff::substitutions;
+
+/// Executes a single pre-exec action. All the paths and arguments of the action are
+/// subject to the same substitutions as the manifest "argv" and "env" sections.
+pub fn run<T>(
+    action: &Action,
+    subs: &[T],
+    env: &HashMap<OsString, OsString>,
+) -> Result<(), Box<dyn Error>>
+where
+    T: Fn(&str) -> String,
+{
+    match action {
+        Action::Mkdir { path } => {
+            let path = substitutions::apply(OsStr::new(path), subs);
+            std::fs::create_dir_all(&path)
+                .map_err(|err| format!("mkdir \"{}\": {}", printable(&path), err))?;
+        }
+        Action::RequireFile { path } => {
+            let path = substitutions::apply(OsStr::new(path), subs);
+            if !Path::new(&path).is_file() {
+                return Err(
+                    format!("require_file \"{}\": file not found", printable(&path)).into(),
+                );
+            }
+        }
+        Action::Run { argv } => {
+            let argv = argv
+                .iter()
+                .map(|a| substitutions::apply(OsStr::new(a), subs))
+                .collect::<Vec<_>>();
+
+            if argv.is_empty() {
+                return Err("run: empty argv".into());
+            }
+
+            match kickoff::process::spawn(&argv, env) {
+                Ok(0) => {}
+                Ok(code) => {
+                    return Err(format!(
+                        "run \"{}\": exited with code {}",
+                        printable(&argv[0]),
+                        code
+                    )
+                    .into())
+                }
+                Err(err) => return Err(format!("run \"{}\": {}", printable(&argv[0]), err).into()),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn printable(value: &OsStr) -> &str {
+    value.to_str().unwrap_or("<unprintable>")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{no_subs, TempDir};
+
+    #[test]
+    fn run_when_mkdir_then_creates_directory() {
+        let dir = TempDir::new("hooks-mkdir");
+        let path = dir.join("some").join("nested").join("dir");
+
+        let action = Action::Mkdir {
+            path: path.to_str().unwrap().to_string(),
+        };
+
+        run(&action, &no_subs(), &HashMap::new()).unwrap();
+        assert!(path.is_dir());
+    }
+
+    #[test]
+    fn run_when_mkdir_then_applies_substitutions() {
+        let dir = TempDir::new("hooks-mkdir-subs");
+        let root = dir.join("root");
+        let subs = vec![|x: &str| -> String { x.replace("{dir}", root.to_str().unwrap()) }];
+
+        let action = Action::Mkdir {
+            path: String::from("{dir}"),
+        };
+
+        run(&action, &subs, &HashMap::new()).unwrap();
+        assert!(root.is_dir());
+    }
+
+    #[test]
+    fn run_when_require_file_exists_then_succeeds() {
+        let dir = TempDir::new("hooks-require-file");
+        let path = dir.join("file");
+        std::fs::write(&path, "").unwrap();
+
+        let action = Action::RequireFile {
+            path: path.to_str().unwrap().to_string(),
+        };
+
+        assert!(run(&action, &no_subs(), &HashMap::new()).is_ok());
+    }
+
+    #[test]
+    fn run_when_require_file_missing_then_fails() {
+        let dir = TempDir::new("hooks-require-file-missing");
+        let path = dir.join("file");
+
+        let action = Action::RequireFile {
+            path: path.to_str().unwrap().to_string(),
+        };
+
+        let err = run(&action, &no_subs(), &HashMap::new()).unwrap_err();
+        assert!(err.to_string().contains(path.to_str().unwrap()));
+    }
+
+    #[test]
+    fn run_when_run_argv_empty_then_fails() {
+        let action = Action::Run { argv: Vec::new() };
+        assert!(run(&action, &no_subs(), &HashMap::new()).is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn run_when_run_succeeds_then_succeeds() {
+        let action = Action::Run {
+            argv: vec![
+                String::from("/bin/sh"),
+                String::from("-c"),
+                String::from("exit 0"),
+            ],
+        };
+
+        assert!(run(&action, &no_subs(), &HashMap::new()).is_ok());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn run_when_run_fails_then_reports_exit_code() {
+        let action = Action::Run {
+            argv: vec![
+                String::from("/bin/sh"),
+                String::from("-c"),
+                String::from("exit 3"),
+            ],
+        };
+
+        let err = run(&action, &no_subs(), &HashMap::new()).unwrap_err();
+        assert_eq!(err.to_string(), "run \"/bin/sh\": exited with code 3");
+    }
+}
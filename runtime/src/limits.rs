@@ -0,0 +1,36 @@
+// Copyright (c) 2023 The Nimbus Authors. All rights reserved.
+//
+// The use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+use std::error::Error;
+
+use kickoff::{process::Resource, Limits};
+
+/// Applies the manifest "limits" section to the current process so that the launched
+/// program inherits them.
+pub fn apply(limits: &Limits) -> Result<(), Box<dyn Error>> {
+    let resources = [
+        ("nofile", Resource::NoFile, limits.nofile),
+        ("core", Resource::Core, limits.core),
+        (
+            "address_space",
+            Resource::AddressSpace,
+            limits.address_space,
+        ),
+        ("cpu", Resource::Cpu, limits.cpu),
+    ];
+
+    for (name, resource, limit) in resources {
+        if let Some(limit) = limit {
+            kickoff::process::setrlimit(resource, limit)
+                .map_err(|err| format!("setrlimit({}, {}): {}", name, limit, err))?;
+        }
+    }
+
+    if let Some(mode) = limits.umask_mode()? {
+        kickoff::process::umask(mode);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,124 @@
+// Copyright (c) 2023 The Nimbus Authors. All rights reserved.
+//
+// The use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+use std::{
+    error::Error,
+    ffi::OsStr,
+    fs::{File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    os::unix::io::AsRawFd,
+    time::{Duration, Instant},
+};
+
+use kickoff::Lock;
+
+use kickoff::substitutions;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Acquires the lock described by the manifest "lock" section and records the current
+/// PID in the lock file. The returned file must be kept open until the program is
+/// launched. Its descriptor is inherited across "execve" so that the lock is held for as
+/// long as the program runs. The lock path is subject to the same substitutions as the
+/// manifest "argv" and "env" sections.
+pub fn acquire<T>(lock: &Lock, subs: &[T]) -> Result<File, Box<dyn Error>>
+where
+    T: Fn(&str) -> String,
+{
+    let path = substitutions::apply(OsStr::new(&lock.path), subs);
+    let printable = path.to_str().unwrap_or("<unprintable>");
+
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)
+        .map_err(|err| format!("open \"{}\": {}", printable, err))?;
+
+    let deadline = Instant::now() + Duration::from_secs(lock.timeout.unwrap_or(0));
+
+    while !kickoff::process::try_lock(file.as_raw_fd())? {
+        if Instant::now() >= deadline {
+            return Err(format!("\"{}\" is held by {}", printable, holder(&mut file)).into());
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+
+    file.set_len(0)?;
+    file.seek(SeekFrom::Start(0))?;
+    write!(file, "{}", std::process::id())?;
+
+    kickoff::process::clear_cloexec(file.as_raw_fd())?;
+
+    Ok(file)
+}
+
+fn holder(file: &mut File) -> String {
+    let mut pid = String::new();
+
+    match file.read_to_string(&mut pid) {
+        Ok(_) if !pid.trim().is_empty() => format!("PID {}", pid.trim()),
+        _ => String::from("another process"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{no_subs, TempDir};
+
+    fn lock(dir: &TempDir, timeout: Option<u64>) -> Lock {
+        Lock {
+            path: dir.join("lock").to_str().unwrap().to_string(),
+            timeout,
+        }
+    }
+
+    #[test]
+    fn acquire_when_not_held_then_records_pid() {
+        let dir = TempDir::new("lock-free");
+        let lock = lock(&dir, None);
+
+        let file = acquire(&lock, &no_subs()).unwrap();
+
+        let pid = std::fs::read_to_string(&lock.path).unwrap();
+        assert_eq!(pid, std::process::id().to_string());
+
+        drop(file);
+    }
+
+    #[test]
+    fn acquire_when_held_then_reports_holder() {
+        let dir = TempDir::new("lock-held");
+        let lock = lock(&dir, None);
+
+        let file = acquire(&lock, &no_subs()).unwrap();
+        let err = acquire(&lock, &no_subs()).unwrap_err();
+
+        assert!(err
+            .to_string()
+            .ends_with(&format!("is held by PID {}", std::process::id())));
+
+        drop(file);
+    }
+
+    #[test]
+    fn acquire_when_released_while_waiting_then_succeeds() {
+        let dir = TempDir::new("lock-wait");
+        let lock = lock(&dir, Some(5));
+
+        let file = acquire(&lock, &no_subs()).unwrap();
+
+        let release = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(300));
+            drop(file);
+        });
+
+        assert!(acquire(&lock, &no_subs()).is_ok());
+
+        release.join().unwrap();
+    }
+}
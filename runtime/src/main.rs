@@ -3,14 +3,34 @@
 // The use of this source code is governed by a BSD-style
 // license that can be found in the LICENSE file.
 
-mod substitutions;
+#[cfg(unix)]
+mod daemon;
+mod embedded;
+mod exit;
+mod header;
+mod hooks;
+#[cfg(unix)]
+mod limits;
+#[cfg(unix)]
+mod lock;
+#[cfg(target_os = "linux")]
+mod sandbox;
+#[cfg(target_os = "linux")]
+mod security;
+#[cfg(unix)]
+mod stdio;
+mod supervisor;
+#[cfg(test)]
+mod testing;
+mod timeout;
 
-use kickoff::Manifest;
-use std::ffi::OsString;
+use kickoff::launch::{self, Context};
+use kickoff::{Manifest, Namespace};
 use std::fs::File;
 use std::io::Error as IOError;
 use std::path::Path;
 use std::process::ExitCode;
+use std::time::Instant;
 
 fn read_manifest(exe: &Path) -> Result<Manifest, IOError> {
     let mut file = File::open(&exe)?;
@@ -33,35 +53,233 @@ fn main() -> ExitCode {
         }
     };
 
-    let os_args = std::env::args_os().skip(1);
-    let os_env = std::env::vars_os();
+    // Payloads are only extracted if the manifest references them.
+    let payloads = match embedded::extract_payloads(&exe, &manifest.payload_references()) {
+        Ok(p) => p,
+        Err(err) => {
+            eprintln!("[kickoff.runtime] Failed to extract payloads: {}", err);
+            return ExitCode::from(1);
+        }
+    };
+
+    let context = Context {
+        exe: exe.clone(),
+        dir,
+        payloads,
+        args: std::env::args_os().skip(1).collect(),
+        env: std::env::vars_os().collect(),
+    };
+
+    let subs = match context.substitutions() {
+        Ok(s) => s,
+        Err(err) => {
+            eprintln!("[kickoff.runtime] Failed to set up substitutions: {}", err);
+            return ExitCode::from(1);
+        }
+    };
+
+    let env = launch::env(&manifest, &context, &subs);
+
+    for (i, action) in manifest.pre.iter().enumerate() {
+        if let Err(err) = hooks::run(action, &subs, &env) {
+            eprintln!(
+                "[kickoff.runtime] Failed to run pre-exec action #{}: {}",
+                i, err
+            );
+            return ExitCode::from(1);
+        }
+    }
+
+    // The candidates are only resolved now since pre-exec actions may create them.
+    let mut plan = match launch::resolve(&manifest, &context, &subs, env) {
+        Ok(plan) => plan,
+        Err(err) => {
+            eprintln!("[kickoff.runtime] Failed to resolve the command: {}", err);
+            return ExitCode::from(1);
+        }
+    };
+
+    // IMPORTANT: Errors are reported to the process that started the runtime until it is
+    // marked as ready, right before the program is launched.
+    #[cfg(unix)]
+    let detached = match &manifest.daemon {
+        Some(daemon) => match daemon::start(daemon, &subs) {
+            Ok(detached) => Some(detached),
+            Err(err) => {
+                eprintln!("[kickoff.runtime] Failed to detach: {}", err);
+                return ExitCode::from(1);
+            }
+        },
+        None => None,
+    };
+
+    #[cfg(unix)]
+    if let Some(stdio) = &manifest.stdio {
+        if let Err(err) = stdio::apply(stdio, &subs) {
+            eprintln!("[kickoff.runtime] Failed to set up stdio: {}", err);
+            return ExitCode::from(1);
+        }
+    }
+
+    // IMPORTANT: The lock file must stay open until the program is launched.
+    #[cfg(unix)]
+    let _lock = match &manifest.lock {
+        Some(lock) => match lock::acquire(lock, &subs) {
+            Ok(file) => Some(file),
+            Err(err) => {
+                eprintln!("[kickoff.runtime] Failed to acquire lock: {}", err);
+                return ExitCode::from(1);
+            }
+        },
+        None => None,
+    };
+
+    // IMPORTANT: The embedded program must be loaded after closing any inherited file
+    // descriptors because it may only exist as an open in-memory file.
+    let program = match embedded::load(&exe) {
+        Ok(program) => program,
+        Err(err) => {
+            eprintln!(
+                "[kickoff.runtime] Failed to load the embedded program: {}",
+                err
+            );
+            return ExitCode::from(1);
+        }
+    };
+
+    if let Some(program) = &program {
+        plan.argv.insert(0, program.path());
+    }
+
+    #[cfg(target_os = "linux")]
+    if let Some(sandbox) = &manifest.sandbox {
+        if let Err(err) = sandbox::apply(sandbox, &subs) {
+            eprintln!("[kickoff.runtime] Failed to set up sandbox: {}", err);
+            return ExitCode::from(1);
+        }
+    }
+
+    let previous_cwd = match launch::enter(&plan) {
+        Ok(previous_cwd) => previous_cwd,
+        Err(err) => {
+            eprintln!("[kickoff.runtime] Failed to prepare the command: {}", err);
+            return ExitCode::from(1);
+        }
+    };
+
+    // IMPORTANT: Limits are applied as late as possible so that they constrain the program
+    // rather than the setup of the runtime (e.g. loading the embedded program). Only the
+    // fallback extraction of in-memory programs below still runs under them. However, they
+    // must be applied before the seccomp filter, which may forbid "setrlimit".
+    #[cfg(unix)]
+    if let Some(limits) = &manifest.limits {
+        if let Err(err) = limits::apply(limits) {
+            eprintln!("[kickoff.runtime] Failed to apply limits: {}", err);
+            return ExitCode::from(1);
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    if let Some(security) = &manifest.security {
+        if let Err(err) = security::apply(security, &subs) {
+            eprintln!(
+                "[kickoff.runtime] Failed to apply security settings: {}",
+                err
+            );
+            return ExitCode::from(1);
+        }
+    }
+
+    #[cfg(unix)]
+    if let Some(detached) = detached {
+        if let Err(err) = detached.ready() {
+            eprintln!("[kickoff.runtime] Failed to detach: {}", err);
+            return ExitCode::from(1);
+        }
+    }
+
+    // A new PID namespace only applies to the children of the runtime. Thus, the program
+    // must be spawned rather than replacing the runtime via "execve".
+    let new_pid_ns = manifest
+        .sandbox
+        .as_ref()
+        .is_some_and(|s| s.namespaces.contains(&Namespace::Pid));
+
+    let spawn = manifest.exit.is_some()
+        || manifest.restart.is_some()
+        || manifest.timeout.is_some()
+        || new_pid_ns;
+
+    if spawn {
+        #[cfg(unix)]
+        if let Err(err) = kickoff::process::forward_termination() {
+            eprintln!(
+                "[kickoff.runtime] Failed to install signal handlers: {}",
+                err
+            );
+            return ExitCode::from(1);
+        }
+
+        let result = match &manifest.restart {
+            Some(restart) => supervisor::supervise(
+                restart,
+                &mut supervisor::ProcessHost {
+                    argv: &plan.argv,
+                    env: &plan.env,
+                    timeout: manifest.timeout.as_ref(),
+                    start: Instant::now(),
+                },
+            ),
+            None => timeout::spawn(&plan.argv, &plan.env, manifest.timeout.as_ref()),
+        };
 
-    let subs = vec![
-        substitutions::fs::current_exe(&exe).unwrap(),
-        substitutions::fs::current_dir(&dir).unwrap(),
-    ];
+        return match result {
+            Ok(code) => match &manifest.exit {
+                Some(exit) => {
+                    let (code, message) = exit::remap(exit, code);
+                    if let Some(message) = message {
+                        eprintln!("{}", message);
+                    }
+                    ExitCode::from(exit::to_exit_code(code))
+                }
+                None => ExitCode::from(exit::to_exit_code(code)),
+            },
+            Err(err) => {
+                eprintln!(
+                    "[kickoff.runtime] Failed to spawn \"{}\": {}",
+                    plan.argv[0].to_str().unwrap_or("<unprintable>"),
+                    err
+                );
+                ExitCode::from(1)
+            }
+        };
+    }
 
-    let manifest_args = manifest
-        .argv
-        .iter()
-        .map(|a| OsString::from(a))
-        .map(|a| substitutions::apply(&a, &subs));
+    // Some systems forbid executing in-memory files. If so, fall back to the cache.
+    #[cfg(target_os = "linux")]
+    if let Some(embedded::Program::Memory(file)) = &program {
+        use std::os::unix::io::AsRawFd;
 
-    let manifest_env = manifest
-        .env
-        .iter()
-        .map(|(k, v)| (OsString::from(k), OsString::from(v)))
-        .map(|(k, v)| (k, substitutions::apply(&v, &subs)));
+        let _ = kickoff::process::fexecve(file.as_raw_fd(), &plan.argv, &plan.env);
 
-    let argv = manifest_args.chain(os_args).collect::<Vec<_>>();
-    let env = os_env.chain(manifest_env).collect();
+        plan.argv[0] = match embedded::extract(&exe) {
+            Ok(path) => path.into_os_string(),
+            Err(err) => {
+                eprintln!(
+                    "[kickoff.runtime] Failed to extract the embedded program: {}",
+                    err
+                );
+                return ExitCode::from(1);
+            }
+        };
+    }
 
-    let error = kickoff::process::execve(&argv, &env).unwrap_err();
+    let error = launch::replace(&plan, previous_cwd).unwrap_err();
 
     eprintln!(
         "[kickoff.runtime] Failed to call execve(\"{}\", [{}, ...], [...])",
-        argv[0].to_str().unwrap_or("<unprintable>"),
-        argv[0].to_str().unwrap_or("<unprintable>"),
+        plan.argv[0].to_str().unwrap_or("<unprintable>"),
+        plan.argv[0].to_str().unwrap_or("<unprintable>"),
     );
 
     ExitCode::from(error.raw_os_error().unwrap_or(1) as u8)
@@ -0,0 +1,120 @@
+// Copyright (c) 2023 The Nimbus Authors. All rights reserved.
+//
+// The use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+use std::{error::Error, ffi::OsStr};
+
+use kickoff::Sandbox;
+
+use kickoff::substitutions;
+
+/// Moves the runtime into the namespaces requested by the manifest "sandbox" section
+/// and applies its bind mounts. Mount sources and targets are subject to the same
+/// substitutions as the manifest "argv" and "env" sections.
+pub fn apply<T>(sandbox: &Sandbox, subs: &[T]) -> Result<(), Box<dyn Error>>
+where
+    T: Fn(&str) -> String,
+{
+    kickoff::process::unshare(&sandbox.namespaces).map_err(|err| format!("unshare: {}", err))?;
+
+    for mount in &sandbox.mounts {
+        let source = substitutions::apply(OsStr::new(&mount.source), subs);
+        let target = substitutions::apply(OsStr::new(&mount.target), subs);
+
+        kickoff::process::bind_mount(&source, &target, mount.read_only).map_err(|err| {
+            format!(
+                "mount \"{}\" on \"{}\": {}",
+                source.to_str().unwrap_or("<unprintable>"),
+                target.to_str().unwrap_or("<unprintable>"),
+                err
+            )
+        })?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, no_subs, TempDir};
+    use kickoff::{Mount, Namespace};
+
+    // Hosts that don't allow unprivileged user namespaces can't run these tests. Thus,
+    // support is probed by creating one in a forked child first.
+    fn user_namespaces_available() -> bool {
+        let output = testing::run_in_child("true", || {
+            Ok(kickoff::process::unshare(&[Namespace::User])?)
+        });
+
+        let available = output.status.success();
+
+        if !available {
+            eprintln!("skipping: user namespaces are not available");
+        }
+
+        available
+    }
+
+    // IMPORTANT: The kernel refuses to create user namespaces for multi-threaded processes
+    // such as the test harness. Thus, the sandbox is applied in a forked child right before
+    // it executes the given shell script.
+    fn run_sandboxed(sandbox: Sandbox, script: &str) -> bool {
+        let output = testing::run_in_child(script, move || apply(&sandbox, &no_subs()));
+        output.status.success()
+    }
+
+    #[test]
+    fn apply_when_net_namespace_then_isolates_network() {
+        if !user_namespaces_available() {
+            return;
+        }
+
+        let outer = std::fs::read_link("/proc/self/ns/net").unwrap();
+
+        let sandbox = Sandbox {
+            namespaces: vec![Namespace::User, Namespace::Net],
+            mounts: Vec::new(),
+        };
+
+        let script = format!(
+            "test \"$(readlink /proc/self/ns/net)\" != \"{}\"",
+            outer.to_str().unwrap()
+        );
+
+        assert!(run_sandboxed(sandbox, &script));
+    }
+
+    #[test]
+    fn apply_when_read_only_mount_then_cannot_write() {
+        if !user_namespaces_available() {
+            return;
+        }
+
+        let root = TempDir::new("sandbox-mount");
+        let source = root.join("source");
+        let target = root.join("target");
+
+        std::fs::create_dir_all(&source).unwrap();
+        std::fs::create_dir_all(&target).unwrap();
+        std::fs::write(source.join("file"), "").unwrap();
+
+        let sandbox = Sandbox {
+            namespaces: vec![Namespace::User, Namespace::Mount],
+            mounts: vec![Mount {
+                source: source.to_str().unwrap().to_string(),
+                target: target.to_str().unwrap().to_string(),
+                read_only: true,
+            }],
+        };
+
+        let script = format!(
+            "test -f {0}/file && ! touch {0}/other 2>/dev/null",
+            target.to_str().unwrap()
+        );
+
+        assert!(run_sandboxed(sandbox, &script));
+        assert!(!target.join("file").exists());
+    }
+}
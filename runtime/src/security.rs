@@ -0,0 +1,144 @@
+// Copyright (c) 2023 The Nimbus Authors. All rights reserved.
+//
+// The use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+use std::{
+    error::Error,
+    ffi::{OsStr, OsString},
+};
+
+use kickoff::{landlock, seccomp, seccomp::Arch, Landlock, Security};
+
+use kickoff::substitutions;
+
+/// Applies the manifest "security" section to the current process. This must be the last
+/// step before launching the program because both Landlock and the seccomp filter also
+/// restrict the runtime itself. Landlock paths are subject to the same substitutions as
+/// the manifest "argv" and "env" sections.
+pub fn apply<T>(security: &Security, subs: &[T]) -> Result<(), Box<dyn Error>>
+where
+    T: Fn(&str) -> String,
+{
+    let program = match &security.seccomp {
+        Some(s) => {
+            let arch = Arch::current().ok_or("seccomp: unsupported architecture")?;
+            Some(seccomp::compile(s, arch).map_err(|err| format!("seccomp: {}", err))?)
+        }
+        None => None,
+    };
+
+    let landlock = match &security.landlock {
+        Some(l) => supported(l)?.then_some(l),
+        None => None,
+    };
+
+    if security.no_new_privs || program.is_some() || landlock.is_some() {
+        kickoff::process::set_no_new_privs().map_err(|err| format!("no_new_privs: {}", err))?;
+    }
+
+    if let Some(landlock) = landlock {
+        let read_only = apply_all(&landlock.read_only, subs);
+        let read_write = apply_all(&landlock.read_write, subs);
+
+        landlock::restrict(
+            &read_only.iter().map(|p| p.as_os_str()).collect::<Vec<_>>(),
+            &read_write.iter().map(|p| p.as_os_str()).collect::<Vec<_>>(),
+        )
+        .map_err(|err| format!("landlock: {}", err))?;
+    }
+
+    if let Some(program) = program {
+        seccomp::install(&program).map_err(|err| format!("seccomp: {}", err))?;
+    }
+
+    Ok(())
+}
+
+/// Checks whether the kernel supports Landlock. If it doesn't, either fails or prints a
+/// warning depending on whether the restrictions are required.
+fn supported(landlock: &Landlock) -> Result<bool, Box<dyn Error>> {
+    match landlock::abi_version() {
+        Ok(_) => Ok(true),
+        Err(err) if landlock.required => Err(format!("landlock: {}", err).into()),
+        Err(err) => {
+            eprintln!(
+                "[kickoff.runtime] WARNING: Landlock is not available ({}), filesystem access is not restricted",
+                err
+            );
+            Ok(false)
+        }
+    }
+}
+
+fn apply_all<T>(paths: &[String], subs: &[T]) -> Vec<OsString>
+where
+    T: Fn(&str) -> String,
+{
+    paths
+        .iter()
+        .map(|p| substitutions::apply(OsStr::new(p), subs))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, no_subs, TempDir};
+
+    // Hosts that don't support Landlock can't run these tests. Thus, support is probed
+    // up front.
+    fn landlock_available() -> bool {
+        let available = kickoff::landlock::abi_version().is_ok();
+
+        if !available {
+            eprintln!("skipping: landlock is not available");
+        }
+
+        available
+    }
+
+    // IMPORTANT: The restrictions are applied in a forked child right before it executes
+    // the given shell script so that the test harness itself isn't restricted.
+    fn run_restricted(security: Security, script: &str) -> bool {
+        let output = testing::run_in_child(script, move || apply(&security, &no_subs()));
+        output.status.success()
+    }
+
+    #[test]
+    fn apply_when_landlock_then_restricts_writes() {
+        if !landlock_available() {
+            return;
+        }
+
+        let root = TempDir::new("security-landlock");
+        let allowed = root.join("allowed");
+        let denied = root.join("denied");
+
+        std::fs::create_dir_all(&allowed).unwrap();
+        std::fs::create_dir_all(&denied).unwrap();
+
+        let system = ["/bin", "/etc", "/lib", "/lib64", "/usr"]
+            .iter()
+            .filter(|p| std::path::Path::new(p).exists())
+            .map(|p| p.to_string());
+
+        let security = Security {
+            landlock: Some(Landlock {
+                read_only: system.collect(),
+                read_write: vec![allowed.to_str().unwrap().to_string()],
+                required: true,
+            }),
+            ..Default::default()
+        };
+
+        let script = format!(
+            "touch {}/file && ! touch {}/file",
+            allowed.to_str().unwrap(),
+            denied.to_str().unwrap()
+        );
+
+        assert!(run_restricted(security, &script));
+        assert!(!denied.join("file").exists());
+    }
+}
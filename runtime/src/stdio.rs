@@ -0,0 +1,141 @@
+// Copyright (c) 2023 The Nimbus Authors. All rights reserved.
+//
+// The use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+use std::{
+    error::Error,
+    ffi::{OsStr, OsString},
+    fs::{File, OpenOptions},
+    os::unix::io::{AsRawFd, IntoRawFd},
+};
+
+use kickoff::{Redirect, Stdio};
+
+use kickoff::substitutions;
+
+/// Applies the manifest "stdio" section to the current process so that the launched
+/// program inherits the resulting file descriptors. File paths are subject to the same
+/// substitutions as the manifest "argv" and "env" sections.
+pub fn apply<T>(stdio: &Stdio, subs: &[T]) -> Result<(), Box<dyn Error>>
+where
+    T: Fn(&str) -> String,
+{
+    if stdio.close_fds {
+        kickoff::process::close_fds_from(3).map_err(|err| format!("close fds: {}", err))?;
+    }
+
+    let streams = [
+        ("stdin", 0, &stdio.stdin),
+        ("stdout", 1, &stdio.stdout),
+        ("stderr", 2, &stdio.stderr),
+    ];
+
+    for (name, fd, redirect) in streams {
+        let redirect = match redirect {
+            Some(r) => r,
+            None => continue,
+        };
+
+        let path = match redirect {
+            Redirect::Null => OsString::from("/dev/null"),
+            Redirect::File(path) => substitutions::apply(OsStr::new(path), subs),
+        };
+
+        let file = open(&path, fd == 0).map_err(|err| {
+            format!(
+                "{}: open \"{}\": {}",
+                name,
+                path.to_str().unwrap_or("<unprintable>"),
+                err
+            )
+        })?;
+
+        kickoff::process::redirect_fd(file.as_raw_fd(), fd)
+            .map_err(|err| format!("{}: dup2: {}", name, err))?;
+
+        // The file is closed when dropped but the redirected descriptor stays open. Unless
+        // the stream was closed, in which case the file got its descriptor.
+        if file.as_raw_fd() == fd {
+            let _ = file.into_raw_fd();
+        }
+    }
+
+    Ok(())
+}
+
+fn open(path: &OsStr, input: bool) -> Result<File, std::io::Error> {
+    match input {
+        true => OpenOptions::new().read(true).open(path),
+        false => OpenOptions::new().create(true).append(true).open(path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, no_subs, TempDir};
+    use std::os::unix::io::FromRawFd;
+
+    // The descriptors of the test harness must be left alone. Thus, "setup" and "apply"
+    // run in a forked child right before it executes the given shell script.
+    fn run_with_stdio<F>(stdio: Stdio, setup: F, script: &str) -> bool
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        let output = testing::run_in_child(script, move || {
+            setup();
+            apply(&stdio, &no_subs())
+        });
+
+        output.status.success()
+    }
+
+    #[test]
+    fn apply_when_file_redirect_then_writes_to_file() {
+        let dir = TempDir::new("stdio-redirect");
+        let out = dir.join("out.log");
+
+        let stdio = Stdio {
+            stdout: Some(Redirect::File(out.to_str().unwrap().to_string())),
+            ..Default::default()
+        };
+
+        assert!(run_with_stdio(stdio, || (), "echo redirected"));
+        assert_eq!(std::fs::read_to_string(&out).unwrap(), "redirected\n");
+    }
+
+    #[test]
+    fn apply_when_stream_closed_then_keeps_redirect_open() {
+        let dir = TempDir::new("stdio-closed");
+        let out = dir.join("out.log");
+
+        let stdio = Stdio {
+            stdout: Some(Redirect::File(out.to_str().unwrap().to_string())),
+            ..Default::default()
+        };
+
+        // The file opened for stdout gets the lowest free descriptor, i.e. stdout itself.
+        let close_stdout = || drop(unsafe { File::from_raw_fd(1) });
+
+        assert!(run_with_stdio(stdio, close_stdout, "echo redirected"));
+        assert_eq!(std::fs::read_to_string(&out).unwrap(), "redirected\n");
+    }
+
+    #[test]
+    fn apply_when_close_fds_then_closes_inherited_fds() {
+        let stdio = Stdio {
+            close_fds: true,
+            ..Default::default()
+        };
+
+        let leak_fd = || kickoff::process::redirect_fd(2, 9).unwrap();
+
+        assert!(run_with_stdio(stdio, leak_fd, "test ! -e /dev/fd/9"));
+        assert!(run_with_stdio(
+            Stdio::default(),
+            leak_fd,
+            "test -e /dev/fd/9"
+        ));
+    }
+}
@@ -0,0 +1,250 @@
+// Copyright (c) 2023 The Nimbus Authors. All rights reserved.
+//
+// The use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    ffi::OsString,
+    io::Error as IOError,
+    time::{Duration, Instant},
+};
+
+use kickoff::{Restart, RestartPolicy, Timeout};
+
+/// The slice of time the supervisor sleeps for before checking for termination requests.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Abstracts away the processes, the clock and the signals from the supervision logic.
+pub trait Host {
+    /// Runs the program to completion and returns its exit code.
+    fn run(&mut self) -> Result<i32, IOError>;
+
+    /// Returns the time elapsed since the supervision started.
+    fn elapsed(&self) -> Duration;
+
+    /// Sleeps for the given duration. Returns false if interrupted by a stop request.
+    fn sleep(&mut self, duration: Duration) -> bool;
+
+    /// Whether the supervisor has been asked to stop.
+    fn stopping(&self) -> bool;
+}
+
+/// Runs the program and restarts it according to the manifest "restart" section. Returns
+/// the last exit code of the program once it is no longer restarted.
+pub fn supervise<H>(restart: &Restart, host: &mut H) -> Result<i32, IOError>
+where
+    H: Host,
+{
+    let window = Duration::from_secs(restart.window);
+    let initial = Duration::from_millis(restart.backoff_initial);
+    let max = Duration::from_millis(restart.backoff_max);
+
+    let mut backoff = initial;
+    let mut restarts = VecDeque::new();
+
+    loop {
+        let started = host.elapsed();
+        let code = host.run()?;
+        let now = host.elapsed();
+
+        if host.stopping() || (code == 0 && restart.policy == RestartPolicy::OnFailure) {
+            return Ok(code);
+        }
+
+        while restarts.front().is_some_and(|t| now - *t > window) {
+            restarts.pop_front();
+        }
+
+        if restarts.len() >= restart.max_restarts as usize {
+            eprintln!(
+                "[kickoff.runtime] Giving up after {} restarts within {}s",
+                restarts.len(),
+                restart.window
+            );
+            return Ok(code);
+        }
+
+        if now - started > window {
+            backoff = initial;
+        }
+
+        eprintln!(
+            "[kickoff.runtime] Program exited with code {}, restarting in {}ms",
+            code,
+            backoff.as_millis()
+        );
+
+        if !host.sleep(backoff) {
+            return Ok(code);
+        }
+
+        restarts.push_back(now);
+        backoff = std::cmp::min(backoff * 2, max);
+    }
+}
+
+/// Runs the actual program and sleeps in small slices to react to SIGTERM promptly.
+pub struct ProcessHost<'a> {
+    pub argv: &'a [OsString],
+    pub env: &'a HashMap<OsString, OsString>,
+    pub timeout: Option<&'a Timeout>,
+    pub start: Instant,
+}
+
+impl<'a> Host for ProcessHost<'a> {
+    fn run(&mut self) -> Result<i32, IOError> {
+        crate::timeout::spawn(self.argv, self.env, self.timeout)
+    }
+
+    fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    fn sleep(&mut self, duration: Duration) -> bool {
+        let deadline = Instant::now() + duration;
+
+        while Instant::now() < deadline {
+            if self.stopping() {
+                return false;
+            }
+            std::thread::sleep(std::cmp::min(POLL_INTERVAL, deadline - Instant::now()));
+        }
+
+        !self.stopping()
+    }
+
+    fn stopping(&self) -> bool {
+        kickoff::process::termination_requested()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fake program that exits with the scripted codes (one per run), each of them
+    /// after running for the scripted amount of time on a virtual clock.
+    struct FakeHost {
+        runs: VecDeque<(i32, Duration)>,
+        clock: Duration,
+        sleeps: Vec<Duration>,
+        stop_after_runs: Option<usize>,
+        count: usize,
+    }
+
+    impl FakeHost {
+        fn new(runs: &[(i32, u64)]) -> Self {
+            Self {
+                runs: runs
+                    .iter()
+                    .map(|(c, s)| (*c, Duration::from_secs(*s)))
+                    .collect(),
+                clock: Duration::ZERO,
+                sleeps: Vec::new(),
+                stop_after_runs: None,
+                count: 0,
+            }
+        }
+    }
+
+    impl Host for FakeHost {
+        fn run(&mut self) -> Result<i32, IOError> {
+            let (code, duration) = self.runs.pop_front().expect("unexpected restart");
+            self.clock += duration;
+            self.count += 1;
+            Ok(code)
+        }
+
+        fn elapsed(&self) -> Duration {
+            self.clock
+        }
+
+        fn sleep(&mut self, duration: Duration) -> bool {
+            self.clock += duration;
+            self.sleeps.push(duration);
+            !self.stopping()
+        }
+
+        fn stopping(&self) -> bool {
+            self.stop_after_runs.is_some_and(|n| self.count >= n)
+        }
+    }
+
+    fn restart(policy: RestartPolicy, max_restarts: u32) -> Restart {
+        Restart {
+            policy,
+            max_restarts,
+            window: 60,
+            backoff_initial: 100,
+            backoff_max: 400,
+        }
+    }
+
+    #[test]
+    fn supervise_when_succeeds_then_not_restarted() {
+        let mut host = FakeHost::new(&[(0, 1)]);
+
+        let code = supervise(&restart(RestartPolicy::OnFailure, 5), &mut host).unwrap();
+
+        assert_eq!(code, 0);
+        assert!(host.sleeps.is_empty());
+    }
+
+    #[test]
+    fn supervise_when_fails_then_restarted_with_backoff() {
+        let mut host = FakeHost::new(&[(1, 1), (137, 1), (1, 1), (1, 1), (0, 1)]);
+
+        let code = supervise(&restart(RestartPolicy::OnFailure, 5), &mut host).unwrap();
+
+        assert_eq!(code, 0);
+        assert_eq!(
+            host.sleeps,
+            [100, 200, 400, 400].map(Duration::from_millis).to_vec()
+        );
+    }
+
+    #[test]
+    fn supervise_when_always_then_restarted_on_success() {
+        let mut host = FakeHost::new(&[(0, 1), (0, 1), (0, 1)]);
+
+        let code = supervise(&restart(RestartPolicy::Always, 2), &mut host).unwrap();
+
+        assert_eq!(code, 0);
+        assert_eq!(host.sleeps.len(), 2);
+    }
+
+    #[test]
+    fn supervise_when_max_restarts_exceeded_then_gives_up() {
+        let mut host = FakeHost::new(&[(1, 1), (2, 1), (3, 1)]);
+
+        let code = supervise(&restart(RestartPolicy::OnFailure, 2), &mut host).unwrap();
+
+        assert_eq!(code, 3);
+        assert!(host.runs.is_empty());
+    }
+
+    #[test]
+    fn supervise_when_restarts_outside_window_then_keeps_restarting() {
+        let mut host = FakeHost::new(&[(1, 1), (1, 61), (1, 61), (0, 1)]);
+
+        let code = supervise(&restart(RestartPolicy::OnFailure, 1), &mut host).unwrap();
+
+        assert_eq!(code, 0);
+        assert_eq!(
+            host.sleeps,
+            [100, 100, 100].map(Duration::from_millis).to_vec()
+        );
+    }
+
+    #[test]
+    fn supervise_when_stopping_then_not_restarted() {
+        let mut host = FakeHost::new(&[(1, 1), (143, 1)]);
+        host.stop_after_runs = Some(2);
+
+        let code = supervise(&restart(RestartPolicy::OnFailure, 5), &mut host).unwrap();
+
+        assert_eq!(code, 143);
+        assert_eq!(host.sleeps.len(), 1);
+    }
+}
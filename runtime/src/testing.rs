@@ -0,0 +1,65 @@
+// Copyright (c) 2023 The Nimbus Authors. All rights reserved.
+//
+// The use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+// Helpers shared by the unit tests of the runtime.
+
+use std::path::{Path, PathBuf};
+
+/// A directory for the files of a test. It is created empty and removed along with its
+/// content when dropped. Thus, it is also cleaned up when the test fails.
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    /// The name must be unique among the tests of the crate.
+    pub fn new(name: &str) -> Self {
+        let path =
+            std::env::temp_dir().join(format!("kickoff-runtime-{}-{}", std::process::id(), name));
+
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+
+        Self { path }
+    }
+
+    pub fn join<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        self.path.join(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+/// The substitutions for values without placeholders.
+pub fn no_subs() -> Vec<fn(&str) -> String> {
+    Vec::new()
+}
+
+/// Runs the shell script in a forked child once "setup" succeeded in it. It lets tests
+/// change the state of a process (e.g. its descriptors, namespaces or security settings)
+/// without affecting the test harness. Some changes would not even be allowed for it
+/// since it is multi-threaded (e.g. creating user namespaces).
+#[cfg(unix)]
+pub fn run_in_child<F>(script: &str, mut setup: F) -> std::process::Output
+where
+    F: FnMut() -> Result<(), Box<dyn std::error::Error>> + Send + Sync + 'static,
+{
+    use std::{
+        io::Error as IOError,
+        os::unix::process::CommandExt,
+        process::{Command, Stdio},
+    };
+
+    let mut cmd = Command::new("/bin/sh");
+    cmd.arg("-c").arg(script).stdin(Stdio::null());
+
+    unsafe { cmd.pre_exec(move || setup().map_err(|err| IOError::other(err.to_string()))) };
+
+    cmd.output().unwrap()
+}
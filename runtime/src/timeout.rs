@@ -0,0 +1,115 @@
+// Copyright (c) 2023 The Nimbus Authors. All rights reserved.
+//
+// The use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+use std::{collections::HashMap, ffi::OsString, io::Error as IOError, time::Duration};
+
+use kickoff::Timeout;
+
+/// Spawns the program and waits for it to exit, enforcing the manifest "timeout" section
+/// if any. A program that times out is reported with the configured exit code.
+pub fn spawn(
+    argv: &[OsString],
+    env: &HashMap<OsString, OsString>,
+    timeout: Option<&Timeout>,
+) -> Result<i32, IOError> {
+    let timeout = match timeout {
+        Some(t) => t,
+        None => return kickoff::process::spawn(argv, env),
+    };
+
+    let result = kickoff::process::spawn_with_timeout(
+        argv,
+        env,
+        Duration::from_secs(timeout.duration),
+        timeout.signal,
+        Duration::from_secs(timeout.grace),
+    )?;
+
+    match result {
+        Some(code) => Ok(code),
+        None => {
+            eprintln!(
+                "[kickoff.runtime] Program timed out after {}s",
+                timeout.duration
+            );
+            Ok(timeout.exit_code)
+        }
+    }
+}
+
+#[cfg(test)]
+#[cfg(unix)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+    use kickoff::Signal;
+    use std::time::Instant;
+
+    fn sh(script: &str) -> Vec<OsString> {
+        vec![
+            OsString::from("/bin/sh"),
+            OsString::from("-c"),
+            OsString::from(script),
+        ]
+    }
+
+    fn timeout(duration: u64, grace: u64) -> Timeout {
+        Timeout {
+            duration,
+            signal: Signal::Term,
+            grace,
+            exit_code: 124,
+        }
+    }
+
+    #[test]
+    fn spawn_when_exits_in_time_then_reports_code() {
+        let code = spawn(&sh("exit 3"), &HashMap::new(), Some(&timeout(5, 5))).unwrap();
+        assert_eq!(code, 3);
+    }
+
+    #[test]
+    fn spawn_when_times_out_then_reports_timeout_code() {
+        let start = Instant::now();
+
+        let code = spawn(&sh("sleep 10"), &HashMap::new(), Some(&timeout(1, 5))).unwrap();
+
+        assert_eq!(code, 124);
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn spawn_when_signal_ignored_then_kills_after_grace() {
+        let start = Instant::now();
+
+        let code = spawn(
+            &sh("trap '' TERM; sleep 10"),
+            &HashMap::new(),
+            Some(&timeout(1, 1)),
+        )
+        .unwrap();
+
+        assert_eq!(code, 124);
+        assert!(start.elapsed() >= Duration::from_secs(2));
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn spawn_when_times_out_then_stops_the_whole_group() {
+        let dir = TempDir::new("timeout-group");
+        let pidfile = dir.join("pidfile");
+        let script = format!("sleep 30 & echo $! > {}; wait", pidfile.display());
+
+        let code = spawn(&sh(&script), &HashMap::new(), Some(&timeout(1, 1))).unwrap();
+        assert_eq!(code, 124);
+
+        let pid = std::fs::read_to_string(&pidfile).unwrap();
+
+        // Once killed, the orphaned "sleep" is either gone or a zombie awaiting its reaper.
+        let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid.trim()));
+        assert!(stat.map_or(true, |s| s.contains(") Z ")));
+    }
+}
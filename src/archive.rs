@@ -0,0 +1,654 @@
+// Copyright (c) 2023 The Nimbus Authors. All rights reserved.
+//
+// The use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{Error as IOError, ErrorKind as IOErrorKind, Read, Write},
+    path::{Component, Path, PathBuf},
+};
+
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+
+// Archives are gzip compressed tar streams in the POSIX ustar format. Names and link targets
+// that do not fit in the header use the GNU "././@LongLink" extension.
+//
+// See: https://www.gnu.org/software/tar/manual/html_node/Standard.html
+const BLOCK_SIZE: usize = 512;
+
+const LONG_LINK_NAME: &str = "././@LongLink";
+
+const TYPE_FILE: u8 = b'0';
+const TYPE_SYMLINK: u8 = b'2';
+const TYPE_DIR: u8 = b'5';
+const TYPE_LONG_NAME: u8 = b'L';
+const TYPE_LONG_LINK: u8 = b'K';
+
+#[derive(Debug, PartialEq)]
+enum Kind {
+    File,
+    Dir,
+    Symlink(String),
+}
+
+#[derive(Debug)]
+struct Entry {
+    path: String,
+    kind: Kind,
+    mode: u32,
+    size: u64,
+}
+
+/// Writes a compressed archive of the directory tree rooted at "dir". The archive only
+/// depends on the names, content, permissions and symlink targets in the tree. Thus,
+/// archiving the same tree twice produces the exact same bytes.
+pub fn pack<W: Write>(dir: &Path, writer: W) -> Result<W, IOError> {
+    // The gzip header holds no timestamp nor file name unless asked to.
+    let mut encoder = GzEncoder::new(writer, Compression::best());
+
+    let mut paths = Vec::new();
+    collect(dir, &mut Vec::new(), &mut paths)?;
+    paths.sort();
+
+    for path in paths {
+        let full = path.iter().fold(dir.to_path_buf(), |p, c| p.join(c));
+        let metadata = std::fs::symlink_metadata(&full)?;
+
+        let kind = match metadata.file_type() {
+            t if t.is_dir() => Kind::Dir,
+            t if t.is_file() => Kind::File,
+            t if t.is_symlink() => Kind::Symlink(utf8(&std::fs::read_link(&full)?)?),
+            _ => {
+                return Err(IOError::new(
+                    IOErrorKind::Unsupported,
+                    format!("unsupported file type: {}", full.display()),
+                ))
+            }
+        };
+
+        let entry = Entry {
+            path: path.join("/"),
+            size: match kind {
+                Kind::File => metadata.len(),
+                _ => 0,
+            },
+            mode: mode(&metadata, &kind),
+            kind,
+        };
+
+        write_entry(&mut encoder, &entry)?;
+
+        if entry.kind == Kind::File {
+            let copied = std::io::copy(&mut File::open(&full)?, &mut encoder)?;
+
+            if copied != entry.size {
+                return Err(IOError::new(
+                    IOErrorKind::UnexpectedEof,
+                    format!("file changed while being archived: {}", full.display()),
+                ));
+            }
+
+            write_padding(&mut encoder, entry.size)?;
+        }
+    }
+
+    // The end of the archive is marked by two empty blocks.
+    encoder.write_all(&[0; 2 * BLOCK_SIZE])?;
+    encoder.finish()
+}
+
+/// Extracts an archive produced by "pack" into "dest", which is created if missing.
+/// Entries that would end up outside of "dest" are rejected.
+pub fn unpack<R: Read>(reader: R, dest: &Path) -> Result<(), IOError> {
+    std::fs::create_dir_all(dest)?;
+
+    // Directory permissions are applied last so that read-only directories can still be
+    // populated while extracting.
+    let mut dirs = Vec::new();
+
+    walk(reader, |entry, data| {
+        let path = resolve(dest, &entry.path)?;
+
+        // Whatever is already there is replaced rather than written through, since it may
+        // be a symlink pointing anywhere.
+        match std::fs::symlink_metadata(&path) {
+            Ok(metadata) if !metadata.is_dir() => std::fs::remove_file(&path)?,
+            _ => (),
+        }
+
+        match &entry.kind {
+            Kind::Dir => {
+                std::fs::create_dir_all(&path)?;
+                dirs.push((path, entry.mode));
+            }
+            Kind::File => {
+                let mut file = OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .open(&path)?;
+                std::io::copy(data, &mut file)?;
+                set_mode(&path, entry.mode)?;
+            }
+            Kind::Symlink(target) => symlink(target, &path)?,
+        }
+
+        Ok(())
+    })?;
+
+    for (path, mode) in dirs.iter().rev() {
+        set_mode(path, *mode)?;
+    }
+
+    Ok(())
+}
+
+/// Checks that every entry in the archive exists in "dest" with the same type, content,
+/// permissions and symlink target. Files that are not part of the archive are ignored.
+pub fn verify<R: Read>(reader: R, dest: &Path) -> Result<bool, IOError> {
+    let mut matches = true;
+
+    walk(reader, |entry, data| {
+        let path = resolve(dest, &entry.path)?;
+
+        let metadata = match std::fs::symlink_metadata(&path) {
+            Ok(m) => m,
+            Err(_) => {
+                matches = false;
+                return Ok(());
+            }
+        };
+
+        matches &= match &entry.kind {
+            Kind::Dir => metadata.is_dir(),
+            Kind::File => {
+                metadata.is_file() && metadata.len() == entry.size && same_content(data, &path)?
+            }
+            Kind::Symlink(target) => {
+                metadata.file_type().is_symlink()
+                    && std::fs::read_link(&path).is_ok_and(|t| t == Path::new(target))
+            }
+        };
+
+        matches &= mode(&metadata, &entry.kind) == entry.mode;
+
+        Ok(())
+    })?;
+
+    Ok(matches)
+}
+
+/// Whether the file at "path" has exactly the content read from "data".
+fn same_content(data: &mut dyn Read, path: &Path) -> Result<bool, IOError> {
+    let mut file = File::open(path)?;
+
+    let mut expected = vec![0; 64 * 1024];
+    let mut actual = vec![0; 64 * 1024];
+
+    loop {
+        let len = data.read(&mut expected)?;
+
+        if len == 0 {
+            return Ok(file.read(&mut actual)? == 0);
+        }
+
+        match file.read_exact(&mut actual[..len]) {
+            Ok(()) if actual[..len] == expected[..len] => continue,
+            Ok(()) => return Ok(false),
+            Err(err) if err.kind() == IOErrorKind::UnexpectedEof => return Ok(false),
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Collects the paths (as lists of components) of everything beneath "dir".
+fn collect(
+    dir: &Path,
+    prefix: &mut Vec<String>,
+    out: &mut Vec<Vec<String>>,
+) -> Result<(), IOError> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+
+        let name = entry.file_name().into_string().map_err(|name| {
+            IOError::new(
+                IOErrorKind::InvalidData,
+                format!("path is not valid UTF-8: {}", Path::new(&name).display()),
+            )
+        })?;
+
+        prefix.push(name);
+        out.push(prefix.clone());
+
+        if entry.file_type()?.is_dir() {
+            collect(&entry.path(), prefix, out)?;
+        }
+
+        prefix.pop();
+    }
+
+    Ok(())
+}
+
+fn walk<R, F>(reader: R, mut f: F) -> Result<(), IOError>
+where
+    R: Read,
+    F: FnMut(&Entry, &mut dyn Read) -> Result<(), IOError>,
+{
+    let mut reader = GzDecoder::new(reader);
+
+    let mut long_name = None;
+    let mut long_link = None;
+
+    loop {
+        let mut header = [0; BLOCK_SIZE];
+        reader.read_exact(&mut header)?;
+
+        if header.iter().all(|b| *b == 0) {
+            return Ok(());
+        }
+
+        if checksum(&header) != parse_octal(&header[148..156])? {
+            return Err(invalid_data("invalid header checksum"));
+        }
+
+        let size = parse_octal(&header[124..136])?;
+        let typeflag = header[156];
+
+        if typeflag == TYPE_LONG_NAME || typeflag == TYPE_LONG_LINK {
+            let mut value = vec![0; size as usize];
+            reader.read_exact(&mut value)?;
+            skip_padding(&mut reader, size)?;
+
+            let value = parse_string(&value)?;
+
+            match typeflag {
+                TYPE_LONG_NAME => long_name = Some(value),
+                _ => long_link = Some(value),
+            }
+
+            continue;
+        }
+
+        let path = match long_name.take() {
+            Some(name) => name,
+            None => parse_string(&header[0..100])?,
+        };
+
+        let link = match long_link.take() {
+            Some(link) => link,
+            None => parse_string(&header[157..257])?,
+        };
+
+        let kind = match typeflag {
+            TYPE_FILE | 0 => Kind::File,
+            TYPE_DIR => Kind::Dir,
+            TYPE_SYMLINK => Kind::Symlink(link),
+            _ => return Err(invalid_data("unsupported entry type")),
+        };
+
+        let entry = Entry {
+            path: path.trim_end_matches('/').to_string(),
+            kind,
+            mode: parse_octal(&header[100..108])? as u32,
+            size,
+        };
+
+        let mut data = (&mut reader).take(size);
+        f(&entry, &mut data)?;
+
+        // Skip whatever the callback did not read.
+        std::io::copy(&mut data, &mut std::io::sink())?;
+        skip_padding(&mut reader, size)?;
+    }
+}
+
+fn write_entry<W: Write>(writer: &mut W, entry: &Entry) -> Result<(), IOError> {
+    let name = match entry.kind {
+        Kind::Dir => format!("{}/", entry.path),
+        _ => entry.path.clone(),
+    };
+
+    let link = match &entry.kind {
+        Kind::Symlink(target) => target.as_str(),
+        _ => "",
+    };
+
+    if name.len() > 100 {
+        write_long_link(writer, TYPE_LONG_NAME, &name)?;
+    }
+
+    if link.len() > 100 {
+        write_long_link(writer, TYPE_LONG_LINK, link)?;
+    }
+
+    let typeflag = match entry.kind {
+        Kind::File => TYPE_FILE,
+        Kind::Dir => TYPE_DIR,
+        Kind::Symlink(_) => TYPE_SYMLINK,
+    };
+
+    writer.write_all(&header(&name, link, entry.mode, entry.size, typeflag)?)
+}
+
+fn write_long_link<W: Write>(writer: &mut W, typeflag: u8, value: &str) -> Result<(), IOError> {
+    let size = value.len() as u64 + 1;
+
+    writer.write_all(&header(LONG_LINK_NAME, "", 0, size, typeflag)?)?;
+    writer.write_all(value.as_bytes())?;
+    writer.write_all(&[0])?;
+    write_padding(writer, size)
+}
+
+fn header(
+    name: &str,
+    link: &str,
+    mode: u32,
+    size: u64,
+    typeflag: u8,
+) -> Result<[u8; BLOCK_SIZE], IOError> {
+    let mut header = [0; BLOCK_SIZE];
+
+    // Names and links that are too long are stored in preceding "long link" entries.
+    // Thus, truncating them here is fine.
+    let name = &name.as_bytes()[..std::cmp::min(name.len(), 100)];
+    let link = &link.as_bytes()[..std::cmp::min(link.len(), 100)];
+
+    header[0..name.len()].copy_from_slice(name);
+    write_octal(&mut header[100..108], mode as u64)?;
+    write_octal(&mut header[108..116], 0)?; // uid
+    write_octal(&mut header[116..124], 0)?; // gid
+    write_octal(&mut header[124..136], size)?;
+    write_octal(&mut header[136..148], 0)?; // mtime
+    header[156] = typeflag;
+    header[157..157 + link.len()].copy_from_slice(link);
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+
+    let checksum = format!("{:06o}\0 ", checksum(&header));
+    header[148..156].copy_from_slice(checksum.as_bytes());
+
+    Ok(header)
+}
+
+/// Returns the header checksum, which is computed as if the checksum field were spaces.
+fn checksum(header: &[u8; BLOCK_SIZE]) -> u64 {
+    header
+        .iter()
+        .enumerate()
+        .map(|(i, b)| match i {
+            148..=155 => b' ' as u64,
+            _ => *b as u64,
+        })
+        .sum()
+}
+
+fn write_octal(field: &mut [u8], value: u64) -> Result<(), IOError> {
+    let digits = format!("{:0width$o}", value, width = field.len() - 1);
+
+    if digits.len() >= field.len() {
+        return Err(IOError::new(
+            IOErrorKind::InvalidInput,
+            format!("value too large for the archive format: {}", value),
+        ));
+    }
+
+    field[..digits.len()].copy_from_slice(digits.as_bytes());
+
+    Ok(())
+}
+
+fn parse_octal(field: &[u8]) -> Result<u64, IOError> {
+    let digits = parse_string(field)?;
+    let digits = digits.trim_matches(|c| c == ' ' || c == '\0');
+
+    match digits.is_empty() {
+        true => Ok(0),
+        false => u64::from_str_radix(digits, 8).map_err(|_| invalid_data("invalid number")),
+    }
+}
+
+fn parse_string(field: &[u8]) -> Result<String, IOError> {
+    let end = field.iter().position(|b| *b == 0).unwrap_or(field.len());
+
+    String::from_utf8(field[..end].to_vec()).map_err(|_| invalid_data("invalid name"))
+}
+
+fn write_padding<W: Write>(writer: &mut W, size: u64) -> Result<(), IOError> {
+    writer.write_all(&vec![0; padding(size)])
+}
+
+fn skip_padding<R: Read>(reader: &mut R, size: u64) -> Result<(), IOError> {
+    reader.read_exact(&mut vec![0; padding(size)])
+}
+
+fn padding(size: u64) -> usize {
+    (BLOCK_SIZE - (size % BLOCK_SIZE as u64) as usize) % BLOCK_SIZE
+}
+
+/// Joins "path" to "dest" making sure that the result is beneath "dest" and that it is
+/// not reached through a symlink (which could point anywhere).
+fn resolve(dest: &Path, path: &str) -> Result<PathBuf, IOError> {
+    let mut resolved = dest.to_path_buf();
+
+    for component in Path::new(path).components() {
+        match component {
+            Component::Normal(c) => {
+                if std::fs::symlink_metadata(&resolved).is_ok_and(|m| m.file_type().is_symlink()) {
+                    return Err(invalid_data(&format!("entry crosses a symlink: {}", path)));
+                }
+                resolved.push(c)
+            }
+            Component::CurDir => continue,
+            _ => {
+                return Err(invalid_data(&format!(
+                    "entry escapes the destination: {}",
+                    path
+                )))
+            }
+        }
+    }
+
+    match resolved == dest {
+        true => Err(invalid_data("entry has an empty name")),
+        false => Ok(resolved),
+    }
+}
+
+fn utf8(path: &Path) -> Result<String, IOError> {
+    path.to_str().map(String::from).ok_or_else(|| {
+        IOError::new(
+            IOErrorKind::InvalidData,
+            format!("path is not valid UTF-8: {}", path.display()),
+        )
+    })
+}
+
+fn invalid_data(message: &str) -> IOError {
+    IOError::new(IOErrorKind::InvalidData, message)
+}
+
+#[cfg(unix)]
+fn mode(metadata: &std::fs::Metadata, kind: &Kind) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+
+    match kind {
+        // Symlink permissions are meaningless on most platforms.
+        Kind::Symlink(_) => 0o777,
+        _ => metadata.permissions().mode() & 0o7777,
+    }
+}
+
+#[cfg(windows)]
+fn mode(metadata: &std::fs::Metadata, kind: &Kind) -> u32 {
+    match (kind, metadata.permissions().readonly()) {
+        (Kind::Symlink(_), _) => 0o777,
+        (Kind::Dir, _) => 0o755,
+        (_, true) => 0o444,
+        (_, false) => 0o644,
+    }
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> Result<(), IOError> {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+}
+
+#[cfg(windows)]
+fn set_mode(path: &Path, mode: u32) -> Result<(), IOError> {
+    let mut permissions = std::fs::metadata(path)?.permissions();
+    permissions.set_readonly(mode & 0o222 == 0);
+
+    std::fs::set_permissions(path, permissions)
+}
+
+#[cfg(unix)]
+fn symlink(target: &str, path: &Path) -> Result<(), IOError> {
+    std::os::unix::fs::symlink(target, path)
+}
+
+#[cfg(windows)]
+fn symlink(_: &str, path: &Path) -> Result<(), IOError> {
+    Err(IOError::new(
+        IOErrorKind::Unsupported,
+        format!("symlinks are not supported: {}", path.display()),
+    ))
+}
+
+#[cfg(test)]
+#[cfg(unix)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn populate(dir: &Path) {
+        let long = "x".repeat(120);
+
+        std::fs::create_dir_all(dir.join("bin")).unwrap();
+        std::fs::create_dir_all(dir.join("lib").join(&long)).unwrap();
+
+        std::fs::write(dir.join("bin/app"), "#!/bin/sh\necho app\n").unwrap();
+        std::fs::set_permissions(dir.join("bin/app"), PermissionsExt::from_mode(0o755)).unwrap();
+
+        std::fs::write(dir.join("lib").join(&long).join("data"), "x".repeat(1000)).unwrap();
+        std::fs::write(dir.join("empty"), "").unwrap();
+
+        std::os::unix::fs::symlink("bin/app", dir.join("link")).unwrap();
+        std::os::unix::fs::symlink(&long, dir.join("long-link-".to_string() + &long)).unwrap();
+    }
+
+    #[test]
+    fn pack_when_called_twice_then_deterministic() {
+        let dir = TempDir::new("archive-deterministic");
+        populate(dir.path());
+
+        let first = pack(dir.path(), Vec::new()).unwrap();
+        let second = pack(dir.path(), Vec::new()).unwrap();
+
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn unpack_when_packed_then_restores_tree() {
+        let dir = TempDir::new("archive-unpack");
+        let src = dir.join("src");
+        let dest = dir.join("dest");
+        populate(&src);
+
+        let archive = pack(&src, Vec::new()).unwrap();
+        unpack(archive.as_slice(), &dest).unwrap();
+
+        let app = dest.join("bin/app");
+        let long = "x".repeat(120);
+
+        assert_eq!(
+            std::fs::read_to_string(&app).unwrap(),
+            "#!/bin/sh\necho app\n"
+        );
+        assert_eq!(app.metadata().unwrap().permissions().mode() & 0o777, 0o755);
+        assert_eq!(
+            std::fs::read(dest.join("lib").join(&long).join("data")).unwrap(),
+            "x".repeat(1000).as_bytes()
+        );
+        assert_eq!(std::fs::read(dest.join("empty")).unwrap(), b"");
+        assert_eq!(
+            std::fs::read_link(dest.join("link")).unwrap(),
+            Path::new("bin/app")
+        );
+        assert_eq!(
+            std::fs::read_link(dest.join("long-link-".to_string() + &long)).unwrap(),
+            Path::new(&long)
+        );
+
+        assert!(verify(archive.as_slice(), &dest).unwrap());
+        assert_eq!(pack(&dest, Vec::new()).unwrap(), archive);
+    }
+
+    #[test]
+    fn verify_when_tree_changed_then_fails() {
+        let dir = TempDir::new("archive-verify");
+        let src = dir.join("src");
+        let dest = dir.join("dest");
+        populate(&src);
+
+        let archive = pack(&src, Vec::new()).unwrap();
+        unpack(archive.as_slice(), &dest).unwrap();
+
+        std::fs::write(dest.join("extra"), "not in the archive").unwrap();
+        assert!(verify(archive.as_slice(), &dest).unwrap());
+
+        std::fs::write(dest.join("bin/app"), "#!/bin/sh\necho bad\n").unwrap();
+        assert!(!verify(archive.as_slice(), &dest).unwrap());
+
+        std::fs::write(dest.join("bin/app"), "truncated").unwrap();
+        assert!(!verify(archive.as_slice(), &dest).unwrap());
+    }
+
+    #[test]
+    fn unpack_when_symlink_in_place_then_replaces_it() {
+        let dir = TempDir::new("archive-replace");
+        let src = dir.join("src");
+        let dest = dir.join("dest");
+        let outside = dir.join("outside");
+        populate(&src);
+
+        std::fs::create_dir_all(dest.join("bin")).unwrap();
+        std::fs::create_dir_all(&outside).unwrap();
+        std::fs::write(outside.join("target"), "untouched").unwrap();
+        std::os::unix::fs::symlink(outside.join("target"), dest.join("bin/app")).unwrap();
+
+        let archive = pack(&src, Vec::new()).unwrap();
+        unpack(archive.as_slice(), &dest).unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(outside.join("target")).unwrap(),
+            "untouched"
+        );
+        assert!(verify(archive.as_slice(), &dest).unwrap());
+    }
+
+    #[test]
+    fn unpack_when_entry_escapes_then_fails() {
+        let dir = TempDir::new("archive-escape");
+        let dest = dir.join("dest");
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        let entry = Entry {
+            path: String::from("../escaped"),
+            kind: Kind::File,
+            mode: 0o644,
+            size: 0,
+        };
+        write_entry(&mut encoder, &entry).unwrap();
+        encoder.write_all(&[0; 2 * BLOCK_SIZE]).unwrap();
+        let archive = encoder.finish().unwrap();
+
+        let err = unpack(archive.as_slice(), &dest).unwrap_err();
+
+        assert_eq!(err.kind(), IOErrorKind::InvalidData);
+        assert!(!dir.join("escaped").exists());
+    }
+}
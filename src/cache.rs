@@ -0,0 +1,467 @@
+// Copyright (c) 2023 The Nimbus Authors. All rights reserved.
+//
+// The use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{Error as IOError, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::archive;
+
+const LAST_USED_FILE: &str = "last-used";
+
+/// A directory of content-addressed entries. Each entry is a directory named after the
+/// SHA-256 digest of its content, so launchers that embed the same payload share it and
+/// existing entries can be verified before being used.
+pub struct Cache {
+    root: PathBuf,
+}
+
+#[derive(Debug)]
+pub struct Entry {
+    pub digest: String,
+    pub size: u64,
+    pub last_used: SystemTime,
+}
+
+/// The outcome of "Cache::gc".
+#[derive(Debug, Default)]
+pub struct Collected {
+    pub removed: Vec<Entry>,
+    /// The stale entries that could not be removed, along with the reason.
+    pub failed: Vec<(Entry, IOError)>,
+}
+
+impl Cache {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// Returns the cache of the current user. It lives in XDG_CACHE_HOME (or ~/.cache) on
+    /// Unix and in LOCALAPPDATA on Windows, unless KICKOFF_CACHE_DIR is set.
+    pub fn user() -> Option<Self> {
+        if let Some(dir) = std::env::var_os("KICKOFF_CACHE_DIR") {
+            return Some(Self::new(dir));
+        }
+
+        #[cfg(unix)]
+        let base = std::env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|h| Path::new(&h).join(".cache")));
+
+        #[cfg(windows)]
+        let base = std::env::var_os("LOCALAPPDATA").map(PathBuf::from);
+
+        base.map(|b| Self::new(b.join("kickoff")))
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Returns the path to an executable file with the given content, storing it first if
+    /// the cache does not have it yet or the stored copy has been tampered with.
+    pub fn store_program(&self, content: &[u8]) -> Result<PathBuf, IOError> {
+        let digest = hex_digest(content);
+        let dir = self.root.join(&digest);
+        let path = dir.join(format!("program{}", std::env::consts::EXE_SUFFIX));
+
+        std::fs::create_dir_all(&self.root)?;
+
+        let _lock = self.lock(&digest, true)?;
+
+        std::fs::create_dir_all(&dir)?;
+
+        if !matches(&path, &digest) {
+            // Write to a temporary file first so that an interrupted extraction never
+            // leaves a partially written program behind.
+            let tmp = dir.join(format!(".program.{}", std::process::id()));
+
+            let written = create_executable(&tmp)
+                .and_then(|mut file| file.write_all(content))
+                .and_then(|_| std::fs::rename(&tmp, &path));
+
+            if written.is_err() {
+                let _ = std::fs::remove_file(&tmp);
+            }
+
+            written?;
+        }
+
+        touch(&dir)?;
+
+        Ok(path)
+    }
+
+    /// Returns the path to a directory with the content of the given archive (see
+    /// "kickoff::archive"), extracting it first if the cache does not have it yet or the
+    /// extracted tree no longer matches the archive.
+    pub fn store_archive(&self, content: &[u8]) -> Result<PathBuf, IOError> {
+        let digest = hex_digest(content);
+        let dir = self.root.join(&digest);
+        let path = dir.join("tree");
+
+        std::fs::create_dir_all(&self.root)?;
+
+        let _lock = self.lock(&digest, true)?;
+
+        std::fs::create_dir_all(&dir)?;
+
+        if !path.is_dir() || !archive::verify(content, &path)? {
+            // Extract to a temporary directory first so that an interrupted extraction
+            // never leaves a partially extracted tree behind.
+            let tmp = dir.join(format!(".tree.{}", std::process::id()));
+            let _ = remove_tree(&tmp);
+
+            let extracted = archive::unpack(content, &tmp)
+                .and_then(|_| match path.exists() {
+                    true => remove_tree(&path),
+                    false => Ok(()),
+                })
+                .and_then(|_| std::fs::rename(&tmp, &path));
+
+            if extracted.is_err() {
+                let _ = remove_tree(&tmp);
+            }
+
+            extracted?;
+        }
+
+        touch(&dir)?;
+
+        Ok(path)
+    }
+
+    /// Returns all the entries in the cache sorted by digest.
+    pub fn entries(&self) -> Result<Vec<Entry>, IOError> {
+        let dirs = match std::fs::read_dir(&self.root) {
+            Ok(dirs) => dirs,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+
+        let mut entries = Vec::new();
+
+        for dir in dirs {
+            let dir = dir?;
+
+            let digest = match dir.file_name().into_string() {
+                Ok(name) if is_digest(&name) && dir.file_type()?.is_dir() => name,
+                _ => continue,
+            };
+
+            entries.push(Entry {
+                digest,
+                size: size(&dir.path())?,
+                last_used: last_used(&dir.path())?,
+            });
+        }
+
+        entries.sort_by(|a, b| a.digest.cmp(&b.digest));
+
+        Ok(entries)
+    }
+
+    /// Removes the entries that have not been used for longer than "max_age". Entries that
+    /// are being extracted at the time are left untouched. Failing to remove an entry does
+    /// not stop the others from being removed.
+    pub fn gc(&self, max_age: Duration) -> Result<Collected, IOError> {
+        let now = SystemTime::now();
+        let mut collected = Collected::default();
+
+        for entry in self.entries()? {
+            let age = now.duration_since(entry.last_used).unwrap_or_default();
+
+            if age <= max_age {
+                continue;
+            }
+
+            let lock = match self.lock(&entry.digest, false)? {
+                Some(lock) => lock,
+                None => continue,
+            };
+
+            // IMPORTANT: The lock file must outlive the entry. Otherwise, a launcher waiting
+            // on it and one creating a new lock file could both extract the entry at once.
+            let result = remove_tree(&self.root.join(&entry.digest));
+            drop(lock);
+
+            match result {
+                Ok(_) => collected.removed.push(entry),
+                Err(err) => collected.failed.push((entry, err)),
+            }
+        }
+
+        Ok(collected)
+    }
+
+    fn lock_path(&self, digest: &str) -> PathBuf {
+        self.root.join(format!("{}.lock", digest))
+    }
+
+    /// Locks the entry for "digest" against concurrent changes. Returns None if "wait" is
+    /// false and the entry is locked already.
+    #[cfg(unix)]
+    fn lock(&self, digest: &str, wait: bool) -> Result<Option<File>, IOError> {
+        use std::os::unix::io::AsRawFd;
+
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(self.lock_path(digest))?;
+
+        let locked = match wait {
+            true => crate::process::lock(file.as_raw_fd()).map(|_| true)?,
+            false => crate::process::try_lock(file.as_raw_fd())?,
+        };
+
+        Ok(locked.then_some(file))
+    }
+
+    // Entries are not locked on Windows. Extractions are still atomic, but concurrent
+    // launchers may extract the same entry more than once.
+    #[cfg(windows)]
+    fn lock(&self, _: &str, _: bool) -> Result<Option<()>, IOError> {
+        Ok(Some(()))
+    }
+}
+
+/// Removes a directory tree, including any read-only directories in it.
+pub(crate) fn remove_tree(path: &Path) -> Result<(), IOError> {
+    make_writable(path)?;
+    std::fs::remove_dir_all(path)
+}
+
+#[cfg(unix)]
+fn make_writable(path: &Path) -> Result<(), IOError> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let metadata = std::fs::symlink_metadata(path)?;
+
+    if !metadata.is_dir() {
+        return Ok(());
+    }
+
+    let mode = metadata.permissions().mode();
+    if mode & 0o700 != 0o700 {
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode | 0o700))?;
+    }
+
+    std::fs::read_dir(path)?.try_for_each(|entry| make_writable(&entry?.path()))
+}
+
+#[cfg(windows)]
+fn make_writable(_: &Path) -> Result<(), IOError> {
+    Ok(())
+}
+
+fn is_digest(name: &str) -> bool {
+    name.len() == 64 && name.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Returns the SHA-256 digest of the given data as a lowercase hex string.
+fn hex_digest(data: &[u8]) -> String {
+    to_hex(&Sha256::digest(data))
+}
+
+fn to_hex(digest: &[u8]) -> String {
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn matches(path: &Path, digest: &str) -> bool {
+    let mut hasher = Sha256::new();
+
+    match File::open(path).and_then(|mut file| std::io::copy(&mut file, &mut hasher)) {
+        Ok(_) => to_hex(&hasher.finalize()) == digest,
+        Err(_) => false,
+    }
+}
+
+fn touch(dir: &Path) -> Result<(), IOError> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+
+    std::fs::write(dir.join(LAST_USED_FILE), now.as_secs().to_string())
+}
+
+fn last_used(dir: &Path) -> Result<SystemTime, IOError> {
+    let recorded = std::fs::read_to_string(dir.join(LAST_USED_FILE))
+        .ok()
+        .and_then(|s| s.trim().parse::<u64>().ok());
+
+    match recorded {
+        Some(secs) => Ok(UNIX_EPOCH + Duration::from_secs(secs)),
+        None => std::fs::metadata(dir)?.modified(),
+    }
+}
+
+fn size(path: &Path) -> Result<u64, IOError> {
+    let metadata = std::fs::symlink_metadata(path)?;
+
+    if !metadata.is_dir() {
+        return Ok(metadata.len());
+    }
+
+    std::fs::read_dir(path)?.try_fold(0, |total, entry| Ok(total + size(&entry?.path())?))
+}
+
+#[cfg(unix)]
+fn create_executable(path: &Path) -> Result<File, IOError> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o755)
+        .open(path)
+}
+
+#[cfg(windows)]
+fn create_executable(path: &Path) -> Result<File, IOError> {
+    OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    fn cache(dir: &TempDir) -> Cache {
+        Cache::new(dir.join("cache"))
+    }
+
+    fn set_last_used(cache: &Cache, digest: &str, time: SystemTime) {
+        let secs = time.duration_since(UNIX_EPOCH).unwrap().as_secs();
+        std::fs::write(
+            cache.root.join(digest).join(LAST_USED_FILE),
+            secs.to_string(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn store_program_when_called_twice_then_reuses_entry() {
+        let dir = TempDir::new("cache-reuse");
+        let cache = cache(&dir);
+
+        let first = cache.store_program(b"some-program").unwrap();
+        let second = cache.store_program(b"some-program").unwrap();
+
+        assert_eq!(first, second);
+        assert_eq!(std::fs::read(&first).unwrap(), b"some-program");
+        assert_eq!(cache.entries().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn store_program_when_entry_tampered_then_restores_it() {
+        let dir = TempDir::new("cache-tampered");
+        let cache = cache(&dir);
+
+        let path = cache.store_program(b"some-program").unwrap();
+        std::fs::write(&path, "something-else").unwrap();
+
+        assert_eq!(cache.store_program(b"some-program").unwrap(), path);
+        assert_eq!(std::fs::read(&path).unwrap(), b"some-program");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn store_archive_when_tree_tampered_then_restores_it() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = TempDir::new("cache-archive");
+        let cache = cache(&dir);
+        let src = dir.join("src");
+
+        std::fs::create_dir_all(src.join("read-only")).unwrap();
+        std::fs::write(src.join("read-only/file"), "some-content").unwrap();
+        std::fs::set_permissions(src.join("read-only"), PermissionsExt::from_mode(0o555)).unwrap();
+
+        let content = archive::pack(&src, Vec::new()).unwrap();
+
+        let path = cache.store_archive(&content).unwrap();
+        assert_eq!(cache.store_archive(&content).unwrap(), path);
+
+        std::fs::set_permissions(path.join("read-only"), PermissionsExt::from_mode(0o755)).unwrap();
+        std::fs::remove_file(path.join("read-only/file")).unwrap();
+
+        assert_eq!(cache.store_archive(&content).unwrap(), path);
+        assert_eq!(
+            std::fs::read_to_string(path.join("read-only/file")).unwrap(),
+            "some-content"
+        );
+    }
+
+    #[test]
+    fn entries_when_root_missing_then_empty() {
+        let dir = TempDir::new("cache-missing");
+        assert!(cache(&dir).entries().unwrap().is_empty());
+    }
+
+    #[test]
+    fn entries_when_stored_then_reports_digest_and_size() {
+        let dir = TempDir::new("cache-entries");
+        let cache = cache(&dir);
+        cache.store_program(b"abc").unwrap();
+
+        let entries = cache.entries().unwrap();
+
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].digest, hex_digest(b"abc"));
+        assert!(entries[0].size >= 3);
+    }
+
+    #[test]
+    fn gc_when_entries_stale_then_removes_only_them() {
+        let dir = TempDir::new("cache-gc");
+        let cache = cache(&dir);
+        let day = Duration::from_secs(24 * 60 * 60);
+
+        cache.store_program(b"old").unwrap();
+        cache.store_program(b"new").unwrap();
+
+        let old = hex_digest(b"old");
+        set_last_used(&cache, &old, SystemTime::now() - 10 * day);
+
+        let collected = cache.gc(7 * day).unwrap();
+
+        assert_eq!(collected.removed.len(), 1);
+        assert_eq!(collected.removed[0].digest, old);
+        assert!(collected.failed.is_empty());
+        assert_eq!(cache.entries().unwrap()[0].digest, hex_digest(b"new"));
+        assert!(cache.lock_path(&old).is_file());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn gc_when_entry_locked_then_skips_it() {
+        let dir = TempDir::new("cache-locked");
+        let cache = cache(&dir);
+        let day = Duration::from_secs(24 * 60 * 60);
+
+        cache.store_program(b"old").unwrap();
+
+        let old = hex_digest(b"old");
+        set_last_used(&cache, &old, SystemTime::now() - 10 * day);
+
+        let lock = cache.lock(&old, true).unwrap();
+
+        assert!(cache.gc(7 * day).unwrap().removed.is_empty());
+        assert_eq!(cache.entries().unwrap().len(), 1);
+
+        drop(lock);
+    }
+}
@@ -0,0 +1,92 @@
+// Copyright (c) 2023 The Nimbus Authors. All rights reserved.
+//
+// The use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+use std::{
+    ffi::{OsStr, OsString},
+    path::Path,
+};
+
+use crate::substitutions;
+
+/// Returns the first candidate that, after substitutions, points to an executable file.
+/// Otherwise, returns the list of all the attempted paths so they can be reported.
+pub fn resolve<T>(candidates: &[String], subs: &[T]) -> Result<OsString, Vec<OsString>>
+where
+    T: Fn(&str) -> String,
+{
+    let attempted = candidates
+        .iter()
+        .map(|c| substitutions::apply(OsStr::new(c), subs))
+        .collect::<Vec<_>>();
+
+    match attempted.iter().find(|c| is_executable(Path::new(c))) {
+        Some(found) => Ok(found.clone()),
+        None => Err(attempted),
+    }
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    match path.metadata() {
+        Ok(m) => m.is_file() && m.permissions().mode() & 0o111 != 0,
+        Err(_) => false,
+    }
+}
+
+#[cfg(windows)]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{no_subs, TempDir};
+
+    #[test]
+    #[cfg(unix)]
+    fn resolve_when_some_candidate_exists_then_picks_first() {
+        let candidates = vec![
+            String::from("/does/not/exist/sh"),
+            String::from("/bin/sh"),
+            String::from("/bin/sh-does-not-exist"),
+        ];
+
+        assert_eq!(resolve(&candidates, &no_subs()).unwrap(), "/bin/sh");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn resolve_when_candidate_not_executable_then_skips() {
+        let dir = TempDir::new("candidates-not-executable");
+        let path = dir.join("candidate");
+        std::fs::write(&path, "").unwrap();
+
+        let candidates = vec![path.to_str().unwrap().to_string(), String::from("/bin/sh")];
+
+        assert_eq!(resolve(&candidates, &no_subs()).unwrap(), "/bin/sh");
+    }
+
+    #[test]
+    fn resolve_when_no_candidate_exists_then_reports_all() {
+        let subs = vec![|x: &str| -> String { x.replace("{dir}", "/some/dir") }];
+        let candidates = vec![String::from("{dir}/one"), String::from("/other/two")];
+
+        assert_eq!(
+            resolve(&candidates, &subs).unwrap_err(),
+            vec![
+                OsString::from("/some/dir/one"),
+                OsString::from("/other/two")
+            ]
+        );
+    }
+
+    #[test]
+    fn resolve_when_no_candidates_then_fails() {
+        assert!(resolve(&[], &no_subs()).unwrap_err().is_empty());
+    }
+}
@@ -0,0 +1,95 @@
+// Copyright (c) 2023 The Nimbus Authors. All rights reserved.
+//
+// The use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+// Runtimes embed a header made of this marker followed by their target triple and their
+// version, separated by ':' and terminated by a NUL byte. It allows telling runtimes apart
+// without running them, which would not even be possible for foreign targets.
+const MARKER: &[u8] = b"k1ck0ff-runtime:";
+
+/// Expands to the header identifying a runtime built for the given target triple. The
+/// version is the one of the crate where the macro is expanded.
+#[macro_export]
+macro_rules! runtime_header {
+    ($triple:literal) => {
+        concat!(
+            "k1ck0ff-runtime:",
+            $triple,
+            ":",
+            env!("CARGO_PKG_VERSION"),
+            "\0"
+        )
+    };
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct Header {
+    pub triple: String,
+    pub version: String,
+}
+
+/// Looks for a runtime header within the given content (e.g. a runtime binary).
+pub fn find(content: &[u8]) -> Option<Header> {
+    // The marker may also appear elsewhere (e.g. in the code that looks for it). Thus,
+    // only the occurrences followed by a well-formed header are considered.
+    (0..content.len())
+        .filter(|&i| content[i..].starts_with(MARKER))
+        .find_map(|i| parse(&content[i + MARKER.len()..]))
+}
+
+fn parse(content: &[u8]) -> Option<Header> {
+    // Headers are short. Thus, anything longer is unlikely to be one.
+    const MAX_LEN: usize = 128;
+
+    let end = content.iter().take(MAX_LEN).position(|b| *b == 0)?;
+    let header = std::str::from_utf8(&content[..end]).ok()?;
+
+    let (triple, version) = header.split_once(':')?;
+
+    let valid_triple = triple.split('-').count() >= 3
+        && triple
+            .split('-')
+            .all(|p| !p.is_empty() && p.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'));
+
+    let valid_version = !version.is_empty()
+        && version
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || ".+-".contains(c));
+
+    match valid_triple && valid_version {
+        true => Some(Header {
+            triple: triple.to_string(),
+            version: version.to_string(),
+        }),
+        false => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_when_header_then_succeeds() {
+        let mut content = b"\x7fELF\0\0k1ck0ff-runtime:garbage".to_vec();
+        content.extend_from_slice(runtime_header!("x86_64-unknown-linux-gnu").as_bytes());
+        content.extend_from_slice(b"\0\x01\x02");
+
+        let header = find(&content).unwrap();
+
+        assert_eq!(header.triple, "x86_64-unknown-linux-gnu");
+        assert_eq!(header.version, env!("CARGO_PKG_VERSION"));
+    }
+
+    #[test]
+    fn find_when_no_header_then_fails() {
+        assert_eq!(find(b""), None);
+        assert_eq!(find(b"k1ck0ff-runtime:"), None);
+        assert_eq!(find(b"k1ck0ff-runtime:x86_64:0.0.1\0"), None);
+        assert_eq!(
+            find(b"k1ck0ff-runtime:x86_64-unknown-linux-gnu:0.0.1"),
+            None
+        );
+    }
+}
@@ -9,8 +9,16 @@ use crate::{Manifest, Section, Trailer};
 
 const MAGIC_NUMBER: &[u8; 8] = b"k1ck0ff!";
 
+/// The layout of the trailer. Must be bumped whenever it changes. The magic number and the
+/// version are always the last bytes of a launcher so that they can be found regardless.
+const TRAILER_VERSION: u64 = 2;
+
 const TRAILER_SIZE: usize = std::mem::size_of::<Trailer>();
 
+/// The size of the first trailer layout, which started with the magic number and had no
+/// version. It only described the runtime and the manifest sections.
+const LEGACY_TRAILER_SIZE: usize = 40;
+
 pub fn read_manifest<T>(reader: &mut T) -> Result<Manifest, IOError>
 where
     T: Read + Seek,
@@ -29,11 +37,94 @@ where
     }
 }
 
+/// Copies the embedded program, if any, into the given writer. Returns the number of
+/// bytes copied, which is zero when the launcher does not embed a program.
+pub fn read_program<T, W>(reader: &mut T, writer: &mut W) -> Result<u64, IOError>
+where
+    T: Read + Seek,
+    W: Write,
+{
+    let trailer = read_trailer(reader)?;
+
+    reader.seek(SeekFrom::Start(trailer.program.pos))?;
+    let copied = std::io::copy(&mut reader.take(trailer.program.len), writer)?;
+
+    match copied == trailer.program.len {
+        true => Ok(copied),
+        false => Err(IOError::from(IOErrorKind::UnexpectedEof)),
+    }
+}
+
+/// Copies the payload with the given name into the given writer. Returns false if the
+/// launcher does not embed such a payload.
+pub fn read_payload<T, W>(reader: &mut T, name: &str, writer: &mut W) -> Result<bool, IOError>
+where
+    T: Read + Seek,
+    W: Write,
+{
+    let trailer = read_trailer(reader)?;
+    let end = trailer
+        .payloads
+        .pos
+        .checked_add(trailer.payloads.len)
+        .ok_or_else(corrupt_payloads)?;
+
+    reader.seek(SeekFrom::Start(trailer.payloads.pos))?;
+
+    while reader.stream_position()? < end {
+        let mut payload_name = vec![0; read_payload_len(reader, end)? as usize];
+        reader.read_exact(&mut payload_name)?;
+
+        let len = read_payload_len(reader, end)?;
+
+        if payload_name != name.as_bytes() {
+            reader.seek(SeekFrom::Current(len as i64))?;
+            continue;
+        }
+
+        return match std::io::copy(&mut reader.take(len), writer)? == len {
+            true => Ok(true),
+            false => Err(IOError::from(IOErrorKind::UnexpectedEof)),
+        };
+    }
+
+    Ok(false)
+}
+
 pub fn write_manifest<T>(writer: &mut T, manifest: &Manifest) -> Result<(), IOError>
 where
     T: Write + Seek,
 {
-    let w_len = writer.seek(SeekFrom::Current(0))?;
+    write_manifest_with_program(writer, &mut std::io::empty(), manifest)
+}
+
+/// Like "write_manifest", but embeds the program read from "program" right after the
+/// runtime so that the launcher does not depend on any other file.
+pub fn write_manifest_with_program<T, R>(
+    writer: &mut T,
+    program: &mut R,
+    manifest: &Manifest,
+) -> Result<(), IOError>
+where
+    T: Write + Seek,
+    R: Read,
+{
+    write_launcher(writer, program, &[], manifest)
+}
+
+/// Like "write_manifest_with_program", but also embeds the given named payloads (e.g.
+/// archives produced by "kickoff::archive::pack").
+pub fn write_launcher<T, R>(
+    writer: &mut T,
+    program: &mut R,
+    payloads: &[(String, Vec<u8>)],
+    manifest: &Manifest,
+) -> Result<(), IOError>
+where
+    T: Write + Seek,
+    R: Read,
+{
+    let w_len = writer.stream_position()?;
 
     let raw_manifest = match serde_json::to_vec(&manifest) {
         Ok(v) => v,
@@ -43,22 +134,46 @@ where
     let runtime_pos = 0;
     let runtime_len = w_len;
 
-    let manifest_pos = w_len;
+    let program_pos = w_len;
+    let program_len = std::io::copy(program, writer)?;
+
+    let payloads_pos = program_pos + program_len;
+    let mut payloads_len = 0;
+
+    for (name, data) in payloads {
+        writer.write_all(&(name.len() as u64).to_ne_bytes())?;
+        writer.write_all(name.as_bytes())?;
+        writer.write_all(&(data.len() as u64).to_ne_bytes())?;
+        writer.write_all(data)?;
+
+        payloads_len += 16 + name.len() as u64 + data.len() as u64;
+    }
+
+    let manifest_pos = payloads_pos + payloads_len;
     let manifest_len = raw_manifest.len() as u64;
 
     let trailer = Trailer {
-        magic: *MAGIC_NUMBER,
         runtime: Section {
             pos: runtime_pos,
             len: runtime_len,
         },
+        program: Section {
+            pos: program_pos,
+            len: program_len,
+        },
+        payloads: Section {
+            pos: payloads_pos,
+            len: payloads_len,
+        },
         manifest: Section {
             pos: manifest_pos,
             len: manifest_len,
         },
+        version: TRAILER_VERSION,
+        magic: *MAGIC_NUMBER,
     };
 
-    writer.write(&raw_manifest[..])?;
+    writer.write_all(&raw_manifest)?;
     write_trailer(writer, &trailer)?;
 
     Ok(())
@@ -70,44 +185,118 @@ where
 {
     let mut trailer = Trailer::default();
 
+    reader.seek(SeekFrom::End(-16))?;
+
+    trailer.version = read_u64(reader)?;
+    reader.read_exact(&mut trailer.magic)?;
+
+    // Check that the obtained magic number and version match the expected ones.
+    // Otherwise, the trailer structure would be populated with meaningless data.
+    if trailer.magic != MAGIC_NUMBER[..] {
+        return Err(match is_legacy_launcher(reader) {
+            true => IOError::new(
+                IOErrorKind::InvalidData,
+                "the launcher was created by an older version of kickoff, recreate it",
+            ),
+            false => IOError::new(IOErrorKind::InvalidData, "not a kickoff launcher"),
+        });
+    }
+
+    if trailer.version != TRAILER_VERSION {
+        return Err(IOError::new(
+            IOErrorKind::InvalidData,
+            format!(
+                "unsupported launcher layout version {} (expected {}), recreate the launcher \
+                 with this version of kickoff",
+                trailer.version, TRAILER_VERSION
+            ),
+        ));
+    }
+
     // Casting from "usize" to "i64" will no longer be safe if the
     // "Trailer" size grows beyond "i64::MAX". This is unlikely to
     // happen in practice. Thus, the current code should be enough
     // for foreseeable future.
     reader.seek(SeekFrom::End(-(TRAILER_SIZE as i64)))?;
 
-    reader.read_exact(&mut trailer.magic)?;
-
     trailer.runtime.pos = read_u64(reader)?;
     trailer.runtime.len = read_u64(reader)?;
 
+    trailer.program.pos = read_u64(reader)?;
+    trailer.program.len = read_u64(reader)?;
+
+    trailer.payloads.pos = read_u64(reader)?;
+    trailer.payloads.len = read_u64(reader)?;
+
     trailer.manifest.pos = read_u64(reader)?;
     trailer.manifest.len = read_u64(reader)?;
 
-    // Check that the obtained magic number matches the expected one.
-    // Otherwiwe, the trailer structure may have been populated with
-    // meaningless data.
-    match trailer.magic == MAGIC_NUMBER[..] {
-        true => Ok(trailer),
-        false => Err(IOError::from(IOErrorKind::InvalidData)),
-    }
+    Ok(trailer)
+}
+
+/// Whether the reader ends with a trailer in the first layout (see "LEGACY_TRAILER_SIZE").
+fn is_legacy_launcher<T>(reader: &mut T) -> bool
+where
+    T: Read + Seek,
+{
+    let mut magic = [0; 8];
+
+    reader
+        .seek(SeekFrom::End(-(LEGACY_TRAILER_SIZE as i64)))
+        .and_then(|_| reader.read_exact(&mut magic))
+        .is_ok_and(|_| magic == MAGIC_NUMBER[..])
+}
+
+/// Whether the given content is a launcher (i.e. ends with a trailer of any layout).
+pub fn is_launcher(content: &[u8]) -> bool {
+    let legacy_magic = content
+        .len()
+        .checked_sub(LEGACY_TRAILER_SIZE)
+        .map(|pos| &content[pos..]);
+
+    content.ends_with(MAGIC_NUMBER) || legacy_magic.is_some_and(|t| t.starts_with(MAGIC_NUMBER))
 }
 
 pub fn write_trailer<T>(writer: &mut T, trailer: &Trailer) -> Result<(), IOError>
 where
     T: Write,
 {
-    writer.write(&trailer.magic)?;
+    writer.write_all(&trailer.runtime.pos.to_ne_bytes())?;
+    writer.write_all(&trailer.runtime.len.to_ne_bytes())?;
+
+    writer.write_all(&trailer.program.pos.to_ne_bytes())?;
+    writer.write_all(&trailer.program.len.to_ne_bytes())?;
 
-    writer.write(&trailer.runtime.pos.to_ne_bytes())?;
-    writer.write(&trailer.runtime.len.to_ne_bytes())?;
+    writer.write_all(&trailer.payloads.pos.to_ne_bytes())?;
+    writer.write_all(&trailer.payloads.len.to_ne_bytes())?;
 
-    writer.write(&trailer.manifest.pos.to_ne_bytes())?;
-    writer.write(&trailer.manifest.len.to_ne_bytes())?;
+    writer.write_all(&trailer.manifest.pos.to_ne_bytes())?;
+    writer.write_all(&trailer.manifest.len.to_ne_bytes())?;
+
+    writer.write_all(&trailer.version.to_ne_bytes())?;
+    writer.write_all(&trailer.magic)?;
 
     Ok(())
 }
 
+/// Reads a length within the payloads section, which ends at "end". A corrupt launcher may
+/// claim any length. Thus, it must not exceed the rest of the section.
+fn read_payload_len<T>(reader: &mut T, end: u64) -> Result<u64, IOError>
+where
+    T: Read + Seek,
+{
+    let len = read_u64(reader)?;
+
+    match len <= end.saturating_sub(reader.stream_position()?) {
+        true => Ok(len),
+        false => Err(corrupt_payloads()),
+    }
+}
+
+fn corrupt_payloads() -> IOError {
+    IOError::new(IOErrorKind::InvalidData, "corrupt payloads section")
+}
+
 fn read_u64<T>(reader: &mut T) -> Result<u64, IOError>
 where
     T: Read,
@@ -128,6 +317,7 @@ mod tests {
         let expected = Manifest {
             argv: vec![String::from("foo"), String::from("bar")],
             env: HashMap::from([(String::from("SOME_KEY"), String::from("some-value"))]),
+            ..Default::default()
         };
 
         let raw_manifest = serde_json::to_string(&expected).unwrap();
@@ -137,13 +327,19 @@ mod tests {
         data.extend_from_slice(&runtime);
         data.extend_from_slice(raw_manifest.as_bytes());
 
-        data.extend_from_slice(MAGIC_NUMBER);
+        data.extend_from_slice(&0_u64.to_ne_bytes());
+        data.extend_from_slice(&(runtime.len() as u64).to_ne_bytes());
 
+        data.extend_from_slice(&(runtime.len() as u64).to_ne_bytes());
         data.extend_from_slice(&0_u64.to_ne_bytes());
+
         data.extend_from_slice(&(runtime.len() as u64).to_ne_bytes());
+        data.extend_from_slice(&0_u64.to_ne_bytes());
 
         data.extend_from_slice(&(runtime.len() as u64).to_ne_bytes());
         data.extend_from_slice(&(raw_manifest.len() as u64).to_ne_bytes());
+        data.extend_from_slice(&TRAILER_VERSION.to_ne_bytes());
+        data.extend_from_slice(MAGIC_NUMBER);
 
         let reader = &mut IOCursor::new(data);
 
@@ -162,13 +358,19 @@ mod tests {
         data.extend_from_slice(&runtime);
         data.extend_from_slice(&manifest);
 
-        data.extend_from_slice(MAGIC_NUMBER);
+        data.extend_from_slice(&0_u64.to_ne_bytes());
+        data.extend_from_slice(&(runtime.len() as u64).to_ne_bytes());
 
+        data.extend_from_slice(&(runtime.len() as u64).to_ne_bytes());
         data.extend_from_slice(&0_u64.to_ne_bytes());
+
         data.extend_from_slice(&(runtime.len() as u64).to_ne_bytes());
+        data.extend_from_slice(&0_u64.to_ne_bytes());
 
         data.extend_from_slice(&(runtime.len() as u64).to_ne_bytes());
         data.extend_from_slice(&(manifest.len() as u64).to_ne_bytes());
+        data.extend_from_slice(&TRAILER_VERSION.to_ne_bytes());
+        data.extend_from_slice(MAGIC_NUMBER);
 
         let reader = &mut IOCursor::new(data);
 
@@ -180,6 +382,7 @@ mod tests {
         let manifest = Manifest {
             argv: vec![String::from("foo"), String::from("bar")],
             env: HashMap::from([(String::from("SOME_KEY"), String::from("some-value"))]),
+            ..Default::default()
         };
 
         let runtime = (0..1024).map(|_| rand::random::<u8>()).collect::<Vec<_>>();
@@ -193,11 +396,16 @@ mod tests {
         let mut want_data = Vec::new();
         want_data.extend_from_slice(&runtime);
         want_data.extend_from_slice(raw_manifest.as_bytes());
-        want_data.extend_from_slice(MAGIC_NUMBER);
         want_data.extend_from_slice(&0_u64.to_ne_bytes());
         want_data.extend_from_slice(&(runtime.len() as u64).to_ne_bytes());
         want_data.extend_from_slice(&(runtime.len() as u64).to_ne_bytes());
+        want_data.extend_from_slice(&0_u64.to_ne_bytes());
+        want_data.extend_from_slice(&(runtime.len() as u64).to_ne_bytes());
+        want_data.extend_from_slice(&0_u64.to_ne_bytes());
+        want_data.extend_from_slice(&(runtime.len() as u64).to_ne_bytes());
         want_data.extend_from_slice(&(raw_manifest.len() as u64).to_ne_bytes());
+        want_data.extend_from_slice(&TRAILER_VERSION.to_ne_bytes());
+        want_data.extend_from_slice(MAGIC_NUMBER);
 
         assert_eq!(want_data, writer.get_ref().clone());
     }
@@ -205,13 +413,19 @@ mod tests {
     #[test]
     fn read_trailer_when_valid_then_succeeds() {
         let mut data = Vec::new();
-        data.extend_from_slice(MAGIC_NUMBER);
-
         data.extend_from_slice(&0_u64.to_ne_bytes());
         data.extend_from_slice(&99_u64.to_ne_bytes());
 
+        data.extend_from_slice(&99_u64.to_ne_bytes());
+        data.extend_from_slice(&1_u64.to_ne_bytes());
+
+        data.extend_from_slice(&100_u64.to_ne_bytes());
+        data.extend_from_slice(&0_u64.to_ne_bytes());
+
         data.extend_from_slice(&100_u64.to_ne_bytes());
         data.extend_from_slice(&120_u64.to_ne_bytes());
+        data.extend_from_slice(&TRAILER_VERSION.to_ne_bytes());
+        data.extend_from_slice(MAGIC_NUMBER);
 
         let trailer = read_trailer(&mut IOCursor::new(data)).unwrap();
 
@@ -220,6 +434,12 @@ mod tests {
         assert_eq!(trailer.runtime.pos, 0);
         assert_eq!(trailer.runtime.len, 99);
 
+        assert_eq!(trailer.program.pos, 99);
+        assert_eq!(trailer.program.len, 1);
+
+        assert_eq!(trailer.payloads.pos, 100);
+        assert_eq!(trailer.payloads.len, 0);
+
         assert_eq!(trailer.manifest.pos, 100);
         assert_eq!(trailer.manifest.len, 120);
     }
@@ -230,20 +450,71 @@ mod tests {
         assert!(read_trailer(&mut IOCursor::new(data)).is_err());
     }
 
+    #[test]
+    fn read_trailer_when_other_version_then_fails() {
+        let mut data = vec![0; TRAILER_SIZE - 16];
+        data.extend_from_slice(&(TRAILER_VERSION + 1).to_ne_bytes());
+        data.extend_from_slice(MAGIC_NUMBER);
+
+        let err = read_trailer(&mut IOCursor::new(data)).unwrap_err();
+
+        assert_eq!(err.kind(), IOErrorKind::InvalidData);
+        assert!(err
+            .to_string()
+            .contains("unsupported launcher layout version"));
+    }
+
+    #[test]
+    fn read_trailer_when_legacy_layout_then_fails() {
+        let mut data = (0..1024).map(|_| rand::random::<u8>()).collect::<Vec<_>>();
+        data.extend_from_slice(MAGIC_NUMBER);
+        data.extend_from_slice(&[0; LEGACY_TRAILER_SIZE - 8]);
+
+        let err = read_trailer(&mut IOCursor::new(data)).unwrap_err();
+
+        assert_eq!(err.kind(), IOErrorKind::InvalidData);
+        assert!(err.to_string().contains("older version of kickoff"));
+    }
+
+    #[test]
+    fn is_launcher_when_trailer_then_true() {
+        let rw = &mut IOCursor::new(b"runtime".to_vec());
+        rw.seek(SeekFrom::End(0)).unwrap();
+        write_manifest(rw, &Manifest::default()).unwrap();
+
+        let mut legacy = b"runtime".to_vec();
+        legacy.extend_from_slice(MAGIC_NUMBER);
+        legacy.extend_from_slice(&[0; LEGACY_TRAILER_SIZE - 8]);
+
+        assert!(is_launcher(rw.get_ref()));
+        assert!(is_launcher(&legacy));
+        assert!(!is_launcher(b"runtime"));
+        assert!(!is_launcher(b""));
+    }
+
     #[test]
     fn write_trailer_when_valid_then_succeeds() {
         let runtime = (0..1024).map(|_| rand::random::<u8>()).collect::<Vec<_>>();
 
         let trailer = Trailer {
-            magic: *MAGIC_NUMBER,
             runtime: Section {
                 pos: 0,
                 len: runtime.len() as u64,
             },
+            program: Section {
+                pos: runtime.len() as u64,
+                len: 0,
+            },
+            payloads: Section {
+                pos: runtime.len() as u64,
+                len: 0,
+            },
             manifest: Section {
                 pos: runtime.len() as u64,
                 len: 0,
             },
+            version: TRAILER_VERSION,
+            magic: *MAGIC_NUMBER,
         };
 
         let writer = &mut IOCursor::new(runtime.clone());
@@ -253,11 +524,16 @@ mod tests {
 
         let mut want_data = Vec::new();
         want_data.extend_from_slice(&runtime);
-        want_data.extend_from_slice(MAGIC_NUMBER);
         want_data.extend_from_slice(&0_u64.to_ne_bytes());
         want_data.extend_from_slice(&(runtime.len() as u64).to_ne_bytes());
         want_data.extend_from_slice(&(runtime.len() as u64).to_ne_bytes());
         want_data.extend_from_slice(&0_u64.to_ne_bytes());
+        want_data.extend_from_slice(&(runtime.len() as u64).to_ne_bytes());
+        want_data.extend_from_slice(&0_u64.to_ne_bytes());
+        want_data.extend_from_slice(&(runtime.len() as u64).to_ne_bytes());
+        want_data.extend_from_slice(&0_u64.to_ne_bytes());
+        want_data.extend_from_slice(&TRAILER_VERSION.to_ne_bytes());
+        want_data.extend_from_slice(MAGIC_NUMBER);
 
         assert_eq!(want_data, writer.get_ref().clone());
     }
@@ -269,10 +545,91 @@ mod tests {
         let want = Manifest {
             argv: vec![String::from("foo"), String::from("bar")],
             env: HashMap::from([(String::from("SOME_KEY"), String::from("some-value"))]),
+            ..Default::default()
         };
 
         write_manifest(rw, &want).unwrap();
 
         assert_eq!(want, read_manifest(rw).unwrap());
     }
+
+    #[test]
+    fn program_serde_roundtrip() {
+        let rw = &mut IOCursor::new(Vec::new());
+
+        let runtime = (0..1024).map(|_| rand::random::<u8>()).collect::<Vec<_>>();
+        let program = (0..1024).map(|_| rand::random::<u8>()).collect::<Vec<_>>();
+        let manifest = Manifest::default();
+
+        rw.write_all(&runtime).unwrap();
+        write_manifest_with_program(rw, &mut program.as_slice(), &manifest).unwrap();
+
+        let mut actual = Vec::new();
+
+        assert!(read_trailer(rw).unwrap().has_program());
+        assert_eq!(read_program(rw, &mut actual).unwrap(), program.len() as u64);
+        assert_eq!(actual, program);
+        assert_eq!(read_manifest(rw).unwrap(), manifest);
+    }
+
+    #[test]
+    fn read_program_when_not_embedded_then_copies_nothing() {
+        let rw = &mut IOCursor::new(Vec::new());
+        write_manifest(rw, &Manifest::default()).unwrap();
+
+        let mut actual = Vec::new();
+
+        assert!(!read_trailer(rw).unwrap().has_program());
+        assert_eq!(read_program(rw, &mut actual).unwrap(), 0);
+        assert!(actual.is_empty());
+    }
+
+    #[test]
+    fn read_payload_when_embedded_then_copies_it() {
+        let rw = &mut IOCursor::new(Vec::new());
+
+        let payloads = vec![
+            (String::from("one"), b"first".to_vec()),
+            (String::from("two"), b"second".to_vec()),
+        ];
+
+        write_launcher(rw, &mut std::io::empty(), &payloads, &Manifest::default()).unwrap();
+
+        let mut actual = Vec::new();
+
+        assert!(read_payload(rw, "two", &mut actual).unwrap());
+        assert_eq!(actual, b"second");
+        assert!(!read_payload(rw, "three", &mut Vec::new()).unwrap());
+        assert_eq!(read_manifest(rw).unwrap(), Manifest::default());
+    }
+
+    #[test]
+    fn read_payload_when_name_length_corrupt_then_fails() {
+        let rw = &mut IOCursor::new(Vec::new());
+        let payloads = vec![(String::from("one"), b"first".to_vec())];
+
+        write_launcher(rw, &mut std::io::empty(), &payloads, &Manifest::default()).unwrap();
+
+        let pos = read_trailer(rw).unwrap().payloads.pos as usize;
+        rw.get_mut()[pos..pos + 8].copy_from_slice(&u64::MAX.to_ne_bytes());
+
+        let err = read_payload(rw, "one", &mut Vec::new()).unwrap_err();
+
+        assert_eq!(err.kind(), IOErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn read_payload_when_length_corrupt_then_fails() {
+        let rw = &mut IOCursor::new(Vec::new());
+        let payloads = vec![(String::from("one"), b"first".to_vec())];
+
+        write_launcher(rw, &mut std::io::empty(), &payloads, &Manifest::default()).unwrap();
+
+        let pos = read_trailer(rw).unwrap().payloads.pos as usize + 8 + 3;
+        rw.get_mut()[pos..pos + 8].copy_from_slice(&1024u64.to_ne_bytes());
+
+        let err = read_payload(rw, "two", &mut Vec::new()).unwrap_err();
+
+        assert_eq!(err.kind(), IOErrorKind::InvalidData);
+    }
 }
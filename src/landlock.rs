@@ -0,0 +1,164 @@
+// Copyright (c) 2023 The Nimbus Authors. All rights reserved.
+//
+// The use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+use std::{
+    ffi::{CString, OsStr},
+    io::Error as IOError,
+    os::unix::ffi::OsStrExt,
+};
+
+// The Landlock syscall numbers are the same on all the supported Linux architectures but
+// not all of them define "libc::SYS_landlock_*" yet.
+const SYS_LANDLOCK_CREATE_RULESET: libc::c_long = 444;
+const SYS_LANDLOCK_ADD_RULE: libc::c_long = 445;
+const SYS_LANDLOCK_RESTRICT_SELF: libc::c_long = 446;
+
+// See: https://github.com/torvalds/linux/blob/master/include/uapi/linux/landlock.h
+const LANDLOCK_CREATE_RULESET_VERSION: u32 = 1 << 0;
+const LANDLOCK_RULE_PATH_BENEATH: libc::c_int = 1;
+
+const ACCESS_FS_EXECUTE: u64 = 1 << 0;
+const ACCESS_FS_WRITE_FILE: u64 = 1 << 1;
+const ACCESS_FS_READ_FILE: u64 = 1 << 2;
+const ACCESS_FS_READ_DIR: u64 = 1 << 3;
+const ACCESS_FS_REFER: u64 = 1 << 13;
+const ACCESS_FS_TRUNCATE: u64 = 1 << 14;
+
+/// The rights introduced by the first version of the Landlock ABI.
+const ACCESS_FS_ABI_V1: u64 = (1 << 13) - 1;
+
+/// The rights that apply to regular files (as opposed to directories).
+const ACCESS_FILE: u64 =
+    ACCESS_FS_EXECUTE | ACCESS_FS_WRITE_FILE | ACCESS_FS_READ_FILE | ACCESS_FS_TRUNCATE;
+
+const ACCESS_READ_ONLY: u64 = ACCESS_FS_EXECUTE | ACCESS_FS_READ_FILE | ACCESS_FS_READ_DIR;
+
+#[repr(C)]
+struct RulesetAttr {
+    handled_access_fs: u64,
+}
+
+#[repr(C, packed)]
+struct PathBeneathAttr {
+    allowed_access: u64,
+    parent_fd: i32,
+}
+
+/// Returns the version of the Landlock ABI supported by the running kernel. Fails with
+/// ENOSYS or EOPNOTSUPP when Landlock is not available.
+pub fn abi_version() -> Result<u32, IOError> {
+    let version = unsafe {
+        libc::syscall(
+            SYS_LANDLOCK_CREATE_RULESET,
+            std::ptr::null::<RulesetAttr>(),
+            0,
+            LANDLOCK_CREATE_RULESET_VERSION,
+        )
+    };
+
+    match version {
+        v if v < 0 => Err(IOError::last_os_error()),
+        v => Ok(v as u32),
+    }
+}
+
+/// Restricts the filesystem access of the current process (and any program it launches
+/// afterwards) to the given paths. Requires the "no_new_privs" bit to be set beforehand.
+pub fn restrict(read_only: &[&OsStr], read_write: &[&OsStr]) -> Result<(), IOError> {
+    let handled = handled_access(abi_version()?);
+
+    let attr = RulesetAttr {
+        handled_access_fs: handled,
+    };
+
+    let ruleset = unsafe {
+        libc::syscall(
+            SYS_LANDLOCK_CREATE_RULESET,
+            &attr as *const RulesetAttr,
+            std::mem::size_of::<RulesetAttr>(),
+            0,
+        )
+    };
+
+    if ruleset < 0 {
+        return Err(IOError::last_os_error());
+    }
+
+    let ruleset = ruleset as libc::c_int;
+
+    let rules = read_only
+        .iter()
+        .map(|p| (p, ACCESS_READ_ONLY & handled))
+        .chain(read_write.iter().map(|p| (p, handled)));
+
+    let mut result = Ok(());
+
+    for (path, access) in rules {
+        result = add_path_rule(ruleset, path, access).map_err(|err| {
+            let path = path.to_str().unwrap_or("<unprintable>");
+            IOError::new(err.kind(), format!("\"{}\": {}", path, err))
+        });
+
+        if result.is_err() {
+            break;
+        }
+    }
+
+    if result.is_ok() && unsafe { libc::syscall(SYS_LANDLOCK_RESTRICT_SELF, ruleset, 0) } != 0 {
+        result = Err(IOError::last_os_error());
+    }
+
+    unsafe { libc::close(ruleset) };
+
+    result
+}
+
+fn handled_access(abi: u32) -> u64 {
+    match abi {
+        0 => 0,
+        1 => ACCESS_FS_ABI_V1,
+        2 => ACCESS_FS_ABI_V1 | ACCESS_FS_REFER,
+        _ => ACCESS_FS_ABI_V1 | ACCESS_FS_REFER | ACCESS_FS_TRUNCATE,
+    }
+}
+
+fn add_path_rule(ruleset: libc::c_int, path: &OsStr, access: u64) -> Result<(), IOError> {
+    let c_path = CString::new(path.as_bytes())?;
+
+    let fd = unsafe { libc::open(c_path.as_ptr(), libc::O_PATH | libc::O_CLOEXEC) };
+    if fd < 0 {
+        return Err(IOError::last_os_error());
+    }
+
+    // The kernel rejects directory-only rights for paths that aren't directories.
+    let access = match std::path::Path::new(path).is_dir() {
+        true => access,
+        false => access & ACCESS_FILE,
+    };
+
+    let attr = PathBeneathAttr {
+        allowed_access: access,
+        parent_fd: fd,
+    };
+
+    let result = unsafe {
+        libc::syscall(
+            SYS_LANDLOCK_ADD_RULE,
+            ruleset,
+            LANDLOCK_RULE_PATH_BENEATH,
+            &attr as *const PathBeneathAttr,
+            0,
+        )
+    };
+
+    let result = match result {
+        0 => Ok(()),
+        _ => Err(IOError::last_os_error()),
+    };
+
+    unsafe { libc::close(fd) };
+
+    result
+}
@@ -0,0 +1,359 @@
+// Copyright (c) 2023 The Nimbus Authors. All rights reserved.
+//
+// The use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+use std::{
+    collections::HashMap,
+    error::Error,
+    ffi::{OsStr, OsString},
+    fmt::{Display, Formatter, Result as FmtResult},
+    io::Error as IOError,
+    path::PathBuf,
+};
+
+use crate::{
+    candidates,
+    substitutions::{self, SubstitutionFn},
+    Manifest,
+};
+
+/// Everything a manifest is launched with besides the manifest itself.
+#[derive(Debug, Clone, Default)]
+pub struct Context {
+    /// Substituted for "{kickoff.self.path}".
+    pub exe: PathBuf,
+    /// Substituted for "{kickoff.self.dir}".
+    pub dir: PathBuf,
+    /// The directories substituted for "{kickoff.payload.<name>}", keyed by name.
+    pub payloads: HashMap<String, PathBuf>,
+    /// The arguments given by the caller, appended after the manifest ones.
+    pub args: Vec<OsString>,
+    /// The environment of the caller, which the manifest one is merged on top of.
+    pub env: HashMap<OsString, OsString>,
+}
+
+impl Context {
+    /// Returns the substitutions that apply to the values in a manifest.
+    pub fn substitutions(&self) -> Result<Vec<Box<SubstitutionFn<'_>>>, Box<dyn Error>> {
+        Ok(vec![
+            substitutions::current_exe(&self.exe)?,
+            substitutions::current_dir(&self.dir)?,
+            substitutions::payload_paths(&self.payloads)?,
+        ])
+    }
+}
+
+/// The command a manifest resolves to, ready to be executed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Plan {
+    /// The executable found among the manifest "exec" candidates. None if the manifest has
+    /// no "exec" section.
+    pub exec: Option<PathBuf>,
+    /// The program followed by its arguments. The program is "exec" if set. Otherwise, it
+    /// is the first manifest argument or, if there are none, the first caller argument.
+    pub argv: Vec<OsString>,
+    pub env: HashMap<OsString, OsString>,
+    /// The directory to run the program in. Unless set, the current one is kept.
+    pub cwd: Option<OsString>,
+}
+
+#[derive(Debug)]
+pub enum PlanError {
+    /// The context cannot be substituted into the manifest (e.g. non UTF-8 paths).
+    Substitutions(String),
+    /// None of the "exec" candidates is executable. Holds the attempted paths.
+    NoExecutable(Vec<OsString>),
+}
+
+impl Display for PlanError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Substitutions(err) => write!(f, "cannot set up substitutions: {}", err),
+            Self::NoExecutable(attempted) => write!(
+                f,
+                "none of the candidates is executable: {}",
+                attempted
+                    .iter()
+                    .map(|c| c.to_str().unwrap_or("<unprintable>"))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+}
+
+impl Error for PlanError {}
+
+/// Resolves the command to run for the given manifest and context. Besides looking for the
+/// "exec" candidates on disk, it has no side effects.
+pub fn plan(manifest: &Manifest, context: &Context) -> Result<Plan, PlanError> {
+    let subs = context
+        .substitutions()
+        .map_err(|err| PlanError::Substitutions(err.to_string()))?;
+    let env = env(manifest, context, &subs);
+
+    resolve(manifest, context, &subs, env)
+}
+
+/// Like [`plan`], but reuses the substitutions and the environment already set up by the
+/// caller (e.g. to run the pre-exec actions before looking for the "exec" candidates).
+pub fn resolve<T>(
+    manifest: &Manifest,
+    context: &Context,
+    subs: &[T],
+    env: HashMap<OsString, OsString>,
+) -> Result<Plan, PlanError>
+where
+    T: Fn(&str) -> String,
+{
+    let argv = argv(manifest, context, subs).map_err(PlanError::NoExecutable)?;
+
+    let exec = match manifest.exec.is_empty() {
+        true => None,
+        false => argv.first().map(PathBuf::from),
+    };
+
+    Ok(Plan {
+        exec,
+        argv,
+        env,
+        cwd: manifest
+            .cwd
+            .as_ref()
+            .map(|cwd| substitutions::apply(OsStr::new(cwd), subs)),
+    })
+}
+
+/// Runs the planned command in place of the current process. Thus, it only returns if
+/// the command cannot be run, in which case the working directory is left unchanged.
+pub fn execute(plan: &Plan) -> Result<(), IOError> {
+    let previous_cwd = enter(plan)?;
+    replace(plan, previous_cwd)
+}
+
+/// Checks that the plan names a program to run and changes to its working directory, if
+/// any. Since the working directory is shared by the whole process, the previous one is
+/// returned so that "replace" can restore it.
+pub fn enter(plan: &Plan) -> Result<Option<PathBuf>, IOError> {
+    if plan.argv.is_empty() {
+        return Err(IOError::new(
+            std::io::ErrorKind::InvalidInput,
+            "the manifest does not name a program to run",
+        ));
+    }
+
+    let cwd = match &plan.cwd {
+        Some(cwd) => cwd,
+        None => return Ok(None),
+    };
+
+    let previous_cwd = std::env::current_dir()?;
+
+    std::env::set_current_dir(cwd).map_err(|err| {
+        IOError::new(
+            err.kind(),
+            format!(
+                "failed to change the working directory to \"{}\": {}",
+                cwd.to_str().unwrap_or("<unprintable>"),
+                err
+            ),
+        )
+    })?;
+
+    Ok(Some(previous_cwd))
+}
+
+/// Runs the planned command in place of the current process once "enter" succeeded. If
+/// the command cannot be run, the working directory is restored to "previous_cwd".
+pub fn replace(plan: &Plan, previous_cwd: Option<PathBuf>) -> Result<(), IOError> {
+    let result = crate::process::execve(&plan.argv, &plan.env);
+
+    if let Some(previous_cwd) = previous_cwd {
+        let _ = std::env::set_current_dir(previous_cwd);
+    }
+
+    result
+}
+
+/// Returns the environment of the caller with the manifest one, after substitutions, on
+/// top of it.
+pub fn env<T>(manifest: &Manifest, context: &Context, subs: &[T]) -> HashMap<OsString, OsString>
+where
+    T: Fn(&str) -> String,
+{
+    let manifest_env = manifest
+        .env
+        .iter()
+        .map(|(k, v)| (OsString::from(k), substitutions::apply(OsStr::new(v), subs)));
+
+    context
+        .env
+        .clone()
+        .into_iter()
+        .chain(manifest_env)
+        .collect()
+}
+
+/// Returns the arguments to launch the program with: the first executable among the "exec"
+/// candidates, if any, followed by the manifest arguments and then the caller ones. If no
+/// candidate is executable, returns the list of the attempted paths instead.
+pub fn argv<T>(
+    manifest: &Manifest,
+    context: &Context,
+    subs: &[T],
+) -> Result<Vec<OsString>, Vec<OsString>>
+where
+    T: Fn(&str) -> String,
+{
+    let exec = match manifest.exec.is_empty() {
+        true => None,
+        false => Some(candidates::resolve(&manifest.exec, subs)?),
+    };
+
+    let manifest_args = manifest
+        .argv
+        .iter()
+        .map(|a| substitutions::apply(OsStr::new(a), subs));
+
+    Ok(exec
+        .into_iter()
+        .chain(manifest_args)
+        .chain(context.args.iter().cloned())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context() -> Context {
+        Context {
+            exe: PathBuf::from("/opt/app/launcher"),
+            dir: PathBuf::from("/opt/app"),
+            payloads: HashMap::from([(String::from("data"), PathBuf::from("/cache/data"))]),
+            args: vec![OsString::from("--caller")],
+            env: HashMap::from([
+                (OsString::from("HOME"), OsString::from("/home/user")),
+                (OsString::from("MODE"), OsString::from("caller")),
+            ]),
+        }
+    }
+
+    #[test]
+    fn env_when_manifest_env_then_overrides_caller_env() {
+        let manifest = Manifest::builder()
+            .arg("/bin/true")
+            .env("MODE", "manifest")
+            .env("DATA", "{kickoff.payload.data}")
+            .build();
+        let context = context();
+        let subs = context.substitutions().unwrap();
+
+        let env = env(&manifest, &context, &subs);
+
+        assert_eq!(env.len(), 3);
+        assert_eq!(env[OsStr::new("HOME")], "/home/user");
+        assert_eq!(env[OsStr::new("MODE")], "manifest");
+        assert_eq!(env[OsStr::new("DATA")], "/cache/data");
+    }
+
+    #[test]
+    fn argv_when_no_exec_then_manifest_args_then_caller_args() {
+        let manifest = Manifest::builder()
+            .arg("{kickoff.self.dir}/bin/app")
+            .arg("--from={kickoff.self.path}")
+            .build();
+        let context = context();
+        let subs = context.substitutions().unwrap();
+
+        let argv = argv(&manifest, &context, &subs).unwrap();
+
+        assert_eq!(
+            argv,
+            vec!["/opt/app/bin/app", "--from=/opt/app/launcher", "--caller"]
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn plan_when_exec_candidate_found_then_resolves() {
+        let manifest = Manifest::builder()
+            .exec("{kickoff.self.dir}/does-not-exist")
+            .exec("/bin/sh")
+            .arg("-c")
+            .env("MODE", "manifest")
+            .cwd("{kickoff.payload.data}")
+            .build();
+
+        let plan = plan(&manifest, &context()).unwrap();
+
+        assert_eq!(plan.exec, Some(PathBuf::from("/bin/sh")));
+        assert_eq!(plan.argv, vec!["/bin/sh", "-c", "--caller"]);
+        assert_eq!(plan.env[OsStr::new("MODE")], "manifest");
+        assert_eq!(plan.cwd, Some(OsString::from("/cache/data")));
+    }
+
+    #[test]
+    fn plan_when_no_exec_then_program_is_first_argument() {
+        let manifest = Manifest::builder()
+            .arg("{kickoff.self.dir}/bin/app")
+            .build();
+
+        let plan = plan(&manifest, &context()).unwrap();
+
+        assert_eq!(plan.exec, None);
+        assert_eq!(plan.argv, vec!["/opt/app/bin/app", "--caller"]);
+    }
+
+    #[test]
+    fn plan_when_no_exec_nor_arguments_then_program_is_first_caller_argument() {
+        let manifest = Manifest::builder().build();
+
+        let plan = plan(&manifest, &context()).unwrap();
+
+        assert_eq!(plan.exec, None);
+        assert_eq!(plan.argv, vec!["--caller"]);
+    }
+
+    #[test]
+    fn plan_when_no_exec_candidate_found_then_fails() {
+        let manifest = Manifest::builder().exec("/does/not/exist").build();
+
+        match plan(&manifest, &context()) {
+            Err(PlanError::NoExecutable(attempted)) => {
+                assert_eq!(attempted, vec!["/does/not/exist"])
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn enter_when_no_program_then_fails() {
+        let plan = Plan {
+            exec: None,
+            argv: Vec::new(),
+            env: HashMap::new(),
+            cwd: Some(OsString::from("/")),
+        };
+
+        let cwd = std::env::current_dir().unwrap();
+        let err = enter(&plan).unwrap_err();
+
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        assert_eq!(std::env::current_dir().unwrap(), cwd);
+    }
+
+    #[test]
+    fn argv_when_no_exec_candidate_found_then_fails() {
+        let manifest = Manifest::builder()
+            .exec("{kickoff.self.dir}/does-not-exist")
+            .build();
+        let context = context();
+        let subs = context.substitutions().unwrap();
+
+        let attempted = argv(&manifest, &context, &subs).unwrap_err();
+
+        assert_eq!(attempted, vec!["/opt/app/does-not-exist"]);
+    }
+}
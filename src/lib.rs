@@ -3,12 +3,23 @@
 // The use of this source code is governed by a BSD-style
 // license that can be found in the LICENSE file.
 
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 
 use serde::{Deserialize, Serialize};
 
+pub mod archive;
+pub mod cache;
+pub mod candidates;
+pub mod header;
 pub mod io;
+#[cfg(target_os = "linux")]
+pub mod landlock;
+pub mod launch;
 pub mod process;
+pub mod seccomp;
+pub mod substitutions;
+#[cfg(test)]
+mod testing;
 
 #[derive(Default, Debug)]
 pub struct Section {
@@ -18,13 +29,678 @@ pub struct Section {
 
 #[derive(Default, Debug)]
 pub struct Trailer {
-    magic: [u8; 8],
     runtime: Section,
+    program: Section,
+    payloads: Section,
     manifest: Section,
+    version: u64,
+    magic: [u8; 8],
+}
+
+impl Trailer {
+    /// Whether the launcher embeds the program it launches.
+    pub fn has_program(&self) -> bool {
+        self.program.len > 0
+    }
 }
 
 #[derive(PartialEq, Default, Debug, Deserialize, Serialize)]
 pub struct Manifest {
+    #[serde(default)]
     pub argv: Vec<String>,
+
+    #[serde(default)]
     pub env: HashMap<String, String>,
+
+    /// Post-processing of the launched program exit code. When present, the runtime
+    /// spawns the program and waits for it instead of replacing itself via "execve".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit: Option<Exit>,
+
+    /// Actions executed in order by the runtime before launching the program. The
+    /// launch is aborted as soon as one of them fails.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pre: Vec<Action>,
+
+    /// Candidate paths for the program to launch, tried in order by the runtime. The
+    /// first one that exists and is executable is used. When set, "argv" only holds
+    /// the arguments passed to the program.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exec: Vec<String>,
+
+    /// Resource limits and file mode creation mask applied by the runtime right before
+    /// launching the program. Only supported on Unix targets.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limits: Option<Limits>,
+
+    /// File descriptor hygiene and standard streams redirection applied by the runtime
+    /// right before launching the program. Only supported on Unix targets.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stdio: Option<Stdio>,
+
+    /// Namespaces and bind mounts set up by the runtime before launching the program.
+    /// Only supported on Linux targets.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sandbox: Option<Sandbox>,
+
+    /// Hardening applied by the runtime right before launching the program. Only
+    /// supported on Linux targets.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub security: Option<Security>,
+
+    /// An exclusive lock acquired by the runtime before launching the program. It is held
+    /// until the program exits. Only supported on Unix targets.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lock: Option<Lock>,
+
+    /// Detaches the program from the terminal and session that started the launcher.
+    /// Only supported on Unix targets.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub daemon: Option<Daemon>,
+
+    /// Supervision policy. When present, the runtime spawns the program and restarts it
+    /// when it exits according to the policy.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub restart: Option<Restart>,
+
+    /// A wall clock limit for the program. When present, the runtime spawns the program
+    /// and stops it once the limit is exceeded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<Timeout>,
+
+    /// The working directory of the program. It is subject to the same substitutions as
+    /// "argv" and "env". By default, the program inherits it from the launcher.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
+}
+
+impl Manifest {
+    pub fn builder() -> ManifestBuilder {
+        ManifestBuilder::new()
+    }
+
+    /// Returns the names of the payloads referenced via "{kickoff.payload.<name>}" in the
+    /// values the runtime substitutes.
+    pub fn payload_references(&self) -> BTreeSet<String> {
+        const PREFIX: &str = "{kickoff.payload.";
+
+        self.substituted_values()
+            .into_iter()
+            .flat_map(|value| {
+                value.match_indices(PREFIX).filter_map(move |(i, _)| {
+                    let rest = &value[i + PREFIX.len()..];
+                    let name = &rest[..rest.find('}')?];
+
+                    is_payload_name(name).then(|| name.to_string())
+                })
+            })
+            .collect()
+    }
+
+    /// Returns the values that are subject to substitutions.
+    fn substituted_values(&self) -> Vec<&String> {
+        let mut values = Vec::new();
+
+        values.extend(&self.argv);
+        values.extend(self.env.values());
+        values.extend(&self.exec);
+        values.extend(&self.cwd);
+
+        for action in &self.pre {
+            match action {
+                Action::Mkdir { path } | Action::RequireFile { path } => values.push(path),
+                Action::Run { argv } => values.extend(argv),
+            }
+        }
+
+        if let Some(stdio) = &self.stdio {
+            for redirect in [&stdio.stdin, &stdio.stdout, &stdio.stderr] {
+                if let Some(Redirect::File(path)) = redirect {
+                    values.push(path);
+                }
+            }
+        }
+
+        if let Some(sandbox) = &self.sandbox {
+            for mount in &sandbox.mounts {
+                values.extend([&mount.source, &mount.target]);
+            }
+        }
+
+        if let Some(landlock) = self.security.as_ref().and_then(|s| s.landlock.as_ref()) {
+            values.extend(&landlock.read_only);
+            values.extend(&landlock.read_write);
+        }
+
+        values.extend(self.lock.as_ref().map(|lock| &lock.path));
+        values.extend(self.daemon.as_ref().and_then(|d| d.pidfile.as_ref()));
+
+        values
+    }
+}
+
+#[derive(PartialEq, Default, Debug, Deserialize, Serialize)]
+pub struct Exit {
+    /// Maps the exit code of the launched program to the one reported by the launcher.
+    #[serde(default)]
+    pub codes: HashMap<i32, i32>,
+
+    /// Messages printed to stderr when the launched program exits with a given code.
+    #[serde(default)]
+    pub messages: HashMap<i32, String>,
+}
+
+#[derive(PartialEq, Default, Debug, Deserialize, Serialize)]
+pub struct Limits {
+    /// The maximum number of open file descriptors (RLIMIT_NOFILE).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nofile: Option<u64>,
+
+    /// The maximum size, in bytes, of core dump files (RLIMIT_CORE).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub core: Option<u64>,
+
+    /// The maximum size, in bytes, of the process virtual memory (RLIMIT_AS).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address_space: Option<u64>,
+
+    /// The maximum amount of CPU time, in seconds, the process can consume (RLIMIT_CPU).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu: Option<u64>,
+
+    /// The file mode creation mask as an octal string (e.g: "022").
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub umask: Option<String>,
+}
+
+impl Limits {
+    /// Parses the octal "umask" string, if any.
+    pub fn umask_mode(&self) -> Result<Option<u32>, String> {
+        let umask = match &self.umask {
+            Some(u) => u,
+            None => return Ok(None),
+        };
+
+        match u32::from_str_radix(umask, 8) {
+            Ok(mode) if mode <= 0o777 => Ok(Some(mode)),
+            _ => Err(format!("invalid umask: {}", umask)),
+        }
+    }
+}
+
+#[derive(PartialEq, Default, Debug, Deserialize, Serialize)]
+pub struct Stdio {
+    /// Closes all the inherited file descriptors above 2 (i.e: stderr).
+    #[serde(default)]
+    pub close_fds: bool,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stdin: Option<Redirect>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stdout: Option<Redirect>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stderr: Option<Redirect>,
+}
+
+#[derive(PartialEq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Redirect {
+    /// Redirects the stream to the null device.
+    Null,
+    /// Redirects the stream to a file. Output streams are appended to it.
+    File(String),
+}
+
+#[derive(PartialEq, Default, Debug, Deserialize, Serialize)]
+pub struct Sandbox {
+    /// The namespaces the program is started in. Unprivileged users need the "user"
+    /// namespace to be able to create any of the others.
+    #[serde(default)]
+    pub namespaces: Vec<Namespace>,
+
+    /// Bind mounts applied in order. Requires the "mount" namespace.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mounts: Vec<Mount>,
+}
+
+#[derive(PartialEq, Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Namespace {
+    User,
+    Mount,
+    Pid,
+    Net,
+}
+
+#[derive(PartialEq, Debug, Deserialize, Serialize)]
+pub struct Mount {
+    pub source: String,
+    pub target: String,
+
+    #[serde(default)]
+    pub read_only: bool,
+}
+
+#[derive(PartialEq, Default, Debug, Deserialize, Serialize)]
+pub struct Security {
+    /// Prevents the program from gaining privileges (e.g: via setuid binaries). This is
+    /// always enabled when a seccomp filter is installed.
+    #[serde(default)]
+    pub no_new_privs: bool,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seccomp: Option<Seccomp>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub landlock: Option<Landlock>,
+}
+
+/// A seccomp-bpf filter given as either an allow-list or a deny-list of syscall names.
+/// Syscalls rejected by the filter fail with EPERM. Note that the filter also applies to
+/// the runtime itself from the moment it is installed (i.e: allow-lists need "execve").
+#[derive(PartialEq, Default, Debug, Deserialize, Serialize)]
+pub struct Seccomp {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allow: Vec<String>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deny: Vec<String>,
+}
+
+/// Filesystem access restrictions enforced with Landlock. The program can only access the
+/// listed paths (and everything beneath them). Any other path is denied.
+#[derive(PartialEq, Default, Debug, Deserialize, Serialize)]
+pub struct Landlock {
+    /// Paths the program can read and execute.
+    #[serde(default)]
+    pub read_only: Vec<String>,
+
+    /// Paths the program can read, execute and modify.
+    #[serde(default)]
+    pub read_write: Vec<String>,
+
+    /// Fails instead of launching the program without restrictions (with a warning) when
+    /// the kernel does not support Landlock.
+    #[serde(default)]
+    pub required: bool,
+}
+
+#[derive(PartialEq, Default, Debug, Deserialize, Serialize)]
+pub struct Lock {
+    /// The lock file path. It is created if it doesn't exist.
+    pub path: String,
+
+    /// How long, in seconds, to wait for the lock to be released by another instance. By
+    /// default, the runtime fails right away if the lock is already held.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
+}
+
+#[derive(PartialEq, Default, Debug, Deserialize, Serialize)]
+pub struct Daemon {
+    /// A file where the PID of the detached program is written to. It stays locked for as
+    /// long as the program runs. Thus, a pidfile left behind by a program that exited is
+    /// not mistaken for a running one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pidfile: Option<String>,
+
+    /// Fails instead of starting the program if "pidfile" is locked by a running one.
+    #[serde(default)]
+    pub refuse_if_running: bool,
+}
+
+#[derive(PartialEq, Debug, Deserialize, Serialize)]
+pub struct Restart {
+    #[serde(default)]
+    pub policy: RestartPolicy,
+
+    /// The maximum number of restarts allowed within "window". The runtime gives up and
+    /// exits with the last exit code of the program once it is exceeded.
+    #[serde(default = "Restart::default_max_restarts")]
+    pub max_restarts: u32,
+
+    /// The length, in seconds, of the sliding window "max_restarts" applies to.
+    #[serde(default = "Restart::default_window")]
+    pub window: u64,
+
+    /// The delay, in milliseconds, before the first restart. It doubles after each
+    /// restart up to "backoff_max" and it is reset once the program runs for longer
+    /// than "window".
+    #[serde(default = "Restart::default_backoff_initial")]
+    pub backoff_initial: u64,
+
+    /// The maximum delay, in milliseconds, between restarts.
+    #[serde(default = "Restart::default_backoff_max")]
+    pub backoff_max: u64,
+}
+
+impl Restart {
+    fn default_max_restarts() -> u32 {
+        5
+    }
+
+    fn default_window() -> u64 {
+        60
+    }
+
+    fn default_backoff_initial() -> u64 {
+        100
+    }
+
+    fn default_backoff_max() -> u64 {
+        30_000
+    }
+}
+
+impl Default for Restart {
+    fn default() -> Self {
+        Self {
+            policy: RestartPolicy::default(),
+            max_restarts: Self::default_max_restarts(),
+            window: Self::default_window(),
+            backoff_initial: Self::default_backoff_initial(),
+            backoff_max: Self::default_backoff_max(),
+        }
+    }
+}
+
+#[derive(PartialEq, Default, Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RestartPolicy {
+    /// Restarts the program when it exits with a non-zero code or crashes.
+    #[default]
+    OnFailure,
+    /// Restarts the program whenever it exits.
+    Always,
+}
+
+#[derive(PartialEq, Debug, Deserialize, Serialize)]
+pub struct Timeout {
+    /// How long, in seconds, the program is allowed to run for.
+    pub duration: u64,
+
+    /// The signal sent to the program once "duration" elapses. On Unix, the program runs
+    /// in a process group of its own and the signal is sent to the whole group. Ignored on
+    /// Windows, where the program is always terminated right away.
+    #[serde(default)]
+    pub signal: Signal,
+
+    /// How long, in seconds, to wait after "signal" before killing the program.
+    #[serde(default = "Timeout::default_grace")]
+    pub grace: u64,
+
+    /// The exit code reported by the launcher when the program times out.
+    #[serde(default = "Timeout::default_exit_code")]
+    pub exit_code: i32,
+}
+
+impl Timeout {
+    fn default_grace() -> u64 {
+        5
+    }
+
+    // Matches the exit code used by the coreutils "timeout" command.
+    fn default_exit_code() -> i32 {
+        124
+    }
+}
+
+/// Builds a "Manifest" step by step. Starting from an existing manifest allows settings
+/// from different sources (e.g. a manifest file and command line flags) to be merged.
+#[derive(Default, Debug)]
+pub struct ManifestBuilder {
+    manifest: Manifest,
+}
+
+impl ManifestBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces the program arguments.
+    pub fn argv<I, S>(mut self, argv: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.manifest.argv = argv.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Appends a single argument to the program arguments.
+    pub fn arg<S: Into<String>>(mut self, arg: S) -> Self {
+        self.manifest.argv.push(arg.into());
+        self
+    }
+
+    /// Sets an environment variable, replacing any previous value.
+    pub fn env<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Self {
+        self.manifest.env.insert(key.into(), value.into());
+        self
+    }
+
+    /// Replaces the candidate paths for the program to launch.
+    pub fn exec_candidates<I, S>(mut self, candidates: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.manifest.exec = candidates.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Appends a candidate path for the program to launch.
+    pub fn exec<S: Into<String>>(mut self, candidate: S) -> Self {
+        self.manifest.exec.push(candidate.into());
+        self
+    }
+
+    pub fn cwd<S: Into<String>>(mut self, cwd: S) -> Self {
+        self.manifest.cwd = Some(cwd.into());
+        self
+    }
+
+    pub fn build(self) -> Manifest {
+        self.manifest
+    }
+}
+
+impl From<Manifest> for ManifestBuilder {
+    fn from(manifest: Manifest) -> Self {
+        Self { manifest }
+    }
+}
+
+/// Whether the given string can be used as the name of a payload.
+pub fn is_payload_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+#[derive(PartialEq, Default, Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Signal {
+    Hup,
+    Int,
+    Quit,
+    Kill,
+    #[default]
+    Term,
+    Usr1,
+    Usr2,
+}
+
+#[derive(PartialEq, Debug, Deserialize, Serialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Action {
+    /// Creates a directory and all of its missing parents.
+    Mkdir { path: String },
+    /// Fails unless the given path exists and is a regular file.
+    RequireFile { path: String },
+    /// Runs a command to completion and fails if it exits with a non-zero code.
+    Run { argv: Vec<String> },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_umask_mode_when_valid_then_succeeds() {
+        let limits = |umask: &str| Limits {
+            umask: Some(String::from(umask)),
+            ..Default::default()
+        };
+
+        assert_eq!(Limits::default().umask_mode(), Ok(None));
+        assert_eq!(limits("022").umask_mode(), Ok(Some(0o022)));
+        assert_eq!(limits("0077").umask_mode(), Ok(Some(0o077)));
+        assert_eq!(limits("777").umask_mode(), Ok(Some(0o777)));
+    }
+
+    #[test]
+    fn limits_umask_mode_when_not_valid_then_fails() {
+        let limits = |umask: &str| Limits {
+            umask: Some(String::from(umask)),
+            ..Default::default()
+        };
+
+        assert!(limits("").umask_mode().is_err());
+        assert!(limits("089").umask_mode().is_err());
+        assert!(limits("1777").umask_mode().is_err());
+        assert!(limits("rwx").umask_mode().is_err());
+    }
+
+    #[test]
+    fn manifest_when_argv_and_env_missing_then_defaults() {
+        let manifest = serde_json::from_str::<Manifest>(r#"{"exec": ["/bin/sh"]}"#).unwrap();
+
+        assert!(manifest.argv.is_empty());
+        assert!(manifest.env.is_empty());
+        assert_eq!(manifest.exec, vec!["/bin/sh"]);
+    }
+
+    #[test]
+    fn stdio_serde_roundtrip() {
+        let raw =
+            r#"{"close_fds":true,"stdin":"null","stdout":{"file":"{kickoff.self.dir}/out.log"}}"#;
+
+        let want = Stdio {
+            close_fds: true,
+            stdin: Some(Redirect::Null),
+            stdout: Some(Redirect::File(String::from("{kickoff.self.dir}/out.log"))),
+            stderr: None,
+        };
+
+        assert_eq!(want, serde_json::from_str::<Stdio>(raw).unwrap());
+        assert_eq!(raw, serde_json::to_string(&want).unwrap());
+    }
+
+    #[test]
+    fn manifest_payload_references_when_present_then_collects_them() {
+        let manifest = Manifest {
+            argv: vec![String::from("{kickoff.payload.app}/main.py")],
+            env: HashMap::from([(
+                String::from("PYTHONPATH"),
+                String::from("{kickoff.payload.lib}:{kickoff.payload.app}"),
+            )]),
+            exec: vec![String::from("{kickoff.payload.python}/bin/python3")],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            manifest.payload_references(),
+            BTreeSet::from([
+                String::from("app"),
+                String::from("lib"),
+                String::from("python")
+            ])
+        );
+    }
+
+    #[test]
+    fn manifest_payload_references_when_in_sections_then_collects_them() {
+        let manifest = Manifest {
+            pre: vec![Action::Run {
+                argv: vec![String::from("{kickoff.payload.hooks}/setup")],
+            }],
+            stdio: Some(Stdio {
+                stdout: Some(Redirect::File(String::from("{kickoff.payload.logs}/out"))),
+                ..Default::default()
+            }),
+            lock: Some(Lock {
+                path: String::from("{kickoff.payload.state}/lock"),
+                timeout: None,
+            }),
+            exit: Some(Exit {
+                messages: HashMap::from([(1, String::from("{kickoff.payload.unused}"))]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            manifest.payload_references(),
+            BTreeSet::from([
+                String::from("hooks"),
+                String::from("logs"),
+                String::from("state")
+            ])
+        );
+    }
+
+    #[test]
+    fn manifest_payload_references_when_malformed_then_ignores_them() {
+        let manifest = Manifest {
+            argv: vec![
+                String::from("{kickoff.payload.}"),
+                String::from("{kickoff.payload.a b}"),
+                String::from("{kickoff.payload.unterminated"),
+            ],
+            ..Default::default()
+        };
+
+        assert!(manifest.payload_references().is_empty());
+    }
+
+    #[test]
+    fn manifest_builder_when_starting_from_manifest_then_merges() {
+        let base = Manifest {
+            argv: vec![String::from("old")],
+            env: HashMap::from([
+                (String::from("KEEP"), String::from("kept")),
+                (String::from("REPLACE"), String::from("old")),
+            ]),
+            ..Default::default()
+        };
+
+        let manifest = ManifestBuilder::from(base)
+            .argv(["/usr/bin/python3", "-m"])
+            .arg("tool")
+            .env("REPLACE", "new")
+            .env("ADD", "added")
+            .cwd("{kickoff.self.dir}")
+            .build();
+
+        let want = Manifest {
+            argv: vec![
+                String::from("/usr/bin/python3"),
+                String::from("-m"),
+                String::from("tool"),
+            ],
+            env: HashMap::from([
+                (String::from("KEEP"), String::from("kept")),
+                (String::from("REPLACE"), String::from("new")),
+                (String::from("ADD"), String::from("added")),
+            ]),
+            cwd: Some(String::from("{kickoff.self.dir}")),
+            ..Default::default()
+        };
+
+        assert_eq!(manifest, want);
+    }
 }
@@ -4,8 +4,30 @@
 // license that can be found in the LICENSE file.
 
 use std::io::Error as IOError;
+use std::process::{Child, Command, ExitStatus};
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::time::{Duration, Instant};
 use std::{collections::HashMap, ffi::OsString};
 
+use crate::Signal;
+
+#[cfg(target_os = "linux")]
+use std::{
+    ffi::{CString, OsStr},
+    os::unix::ffi::OsStrExt,
+};
+
+/// The PID of the child currently running under "spawn" or 0 if there is none. It is
+/// negative if the child leads its own process group (see "spawn_with_timeout"), so that
+/// signals are sent to the whole group.
+static CHILD: AtomicI32 = AtomicI32::new(0);
+
+/// Whether a termination request has been received. See "forward_termination".
+static TERMINATED: AtomicBool = AtomicBool::new(false);
+
+/// How often a child running under "spawn_with_timeout" is checked for completion.
+const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
 #[cfg(windows)]
 pub fn execve(argv: &[OsString], env: &HashMap<OsString, OsString>) -> Result<(), IOError> {
     use std::os::windows::ffi::OsStrExt;
@@ -51,6 +73,33 @@ pub fn execve(argv: &[OsString], env: &HashMap<OsString, OsString>) -> Result<()
 
 #[cfg(unix)]
 pub fn execve(argv: &[OsString], env: &HashMap<OsString, OsString>) -> Result<(), IOError> {
+    with_c_args(argv, env, |argv, env| unsafe {
+        libc::execve(argv[0], argv.as_ptr(), env.as_ptr())
+    });
+
+    Err(IOError::last_os_error())
+}
+
+/// Like "execve", but executes the file referred to by "fd" rather than "argv[0]". This
+/// allows running programs that only exist in memory (see "memfd_create").
+#[cfg(target_os = "linux")]
+pub fn fexecve(
+    fd: i32,
+    argv: &[OsString],
+    env: &HashMap<OsString, OsString>,
+) -> Result<(), IOError> {
+    with_c_args(argv, env, |argv, env| unsafe {
+        libc::fexecve(fd, argv.as_ptr(), env.as_ptr())
+    });
+
+    Err(IOError::last_os_error())
+}
+
+#[cfg(unix)]
+fn with_c_args<F>(argv: &[OsString], env: &HashMap<OsString, OsString>, f: F) -> libc::c_int
+where
+    F: FnOnce(&[*const libc::c_char], &[*const libc::c_char]) -> libc::c_int,
+{
     use std::{ffi::CString, os::unix::ffi::OsStrExt};
 
     // IMPORTANT: It may seem that some of the iteration pipelines below can be combined
@@ -84,7 +133,529 @@ pub fn execve(argv: &[OsString], env: &HashMap<OsString, OsString>) -> Result<()
         .chain(std::iter::once(std::ptr::null()))
         .collect::<Vec<_>>();
 
-    unsafe { libc::execve(argv[0], argv.as_ptr(), env.as_ptr()) };
+    f(&argv, &env)
+}
 
-    Err(IOError::last_os_error())
+/// Runs the program as a child process and waits for it to exit. Unlike "execve", the
+/// caller keeps running and gets the child exit code back. On Unix, a child terminated
+/// by a signal is reported as "128 + signal" following the usual shell convention.
+pub fn spawn(argv: &[OsString], env: &HashMap<OsString, OsString>) -> Result<i32, IOError> {
+    let mut child = start(argv, env, false)?;
+
+    let status = child.wait();
+    CHILD.store(0, Ordering::SeqCst);
+
+    Ok(exit_code(status?))
+}
+
+/// Like "spawn", but stops the child once "timeout" elapses. On Unix, the child is sent
+/// "signal" first and, if it is still running after "grace", it is killed with SIGKILL.
+/// On Windows, the child is terminated right away. Returns None if the child timed out.
+///
+/// On Unix, the child runs in a process group of its own and the signals are sent to the
+/// whole group, like "timeout" from coreutils does. Otherwise, the processes it started
+/// (e.g. those of a shell wrapper) would keep running after the timeout.
+pub fn spawn_with_timeout(
+    argv: &[OsString],
+    env: &HashMap<OsString, OsString>,
+    timeout: Duration,
+    signal: Signal,
+    grace: Duration,
+) -> Result<Option<i32>, IOError> {
+    let mut child = start(argv, env, true)?;
+
+    let result = match wait_until(&mut child, Instant::now() + timeout) {
+        Ok(Some(status)) => Ok(Some(exit_code(status))),
+        Ok(None) => stop(&mut child, signal, grace).map(|_| None),
+        Err(err) => Err(err),
+    };
+
+    CHILD.store(0, Ordering::SeqCst);
+
+    result
+}
+
+fn start(
+    argv: &[OsString],
+    env: &HashMap<OsString, OsString>,
+    own_group: bool,
+) -> Result<Child, IOError> {
+    let mut command = Command::new(&argv[0]);
+    command.args(&argv[1..]).env_clear().envs(env);
+
+    #[cfg(unix)]
+    if own_group {
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
+    }
+
+    let child = command.spawn()?;
+
+    let pid = child.id() as i32;
+    CHILD.store(
+        if cfg!(unix) && own_group { -pid } else { pid },
+        Ordering::SeqCst,
+    );
+
+    Ok(child)
+}
+
+/// Waits for the child to exit until the deadline. Returns None if it is still running.
+fn wait_until(child: &mut Child, deadline: Instant) -> Result<Option<ExitStatus>, IOError> {
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+
+        let now = Instant::now();
+        if now >= deadline {
+            return Ok(None);
+        }
+
+        std::thread::sleep(std::cmp::min(WAIT_POLL_INTERVAL, deadline - now));
+    }
+}
+
+/// Stops the child started by "spawn_with_timeout" along with the rest of its process
+/// group.
+#[cfg(unix)]
+fn stop(child: &mut Child, signal: Signal, grace: Duration) -> Result<(), IOError> {
+    let group = -(child.id() as i32);
+
+    if unsafe { libc::kill(group, signal_number(signal)) } != 0 {
+        return Err(IOError::last_os_error());
+    }
+
+    if wait_until(child, Instant::now() + grace)?.is_none() {
+        if unsafe { libc::kill(group, libc::SIGKILL) } != 0 {
+            return Err(IOError::last_os_error());
+        }
+        child.wait()?;
+    }
+
+    Ok(())
+}
+
+#[cfg(windows)]
+fn stop(child: &mut Child, _: Signal, _: Duration) -> Result<(), IOError> {
+    child.kill()?;
+    child.wait()?;
+    Ok(())
+}
+
+/// Returns the number the current platform uses for the given signal.
+#[cfg(unix)]
+pub fn signal_number(signal: Signal) -> i32 {
+    match signal {
+        Signal::Hup => libc::SIGHUP,
+        Signal::Int => libc::SIGINT,
+        Signal::Quit => libc::SIGQUIT,
+        Signal::Kill => libc::SIGKILL,
+        Signal::Term => libc::SIGTERM,
+        Signal::Usr1 => libc::SIGUSR1,
+        Signal::Usr2 => libc::SIGUSR2,
+    }
+}
+
+/// Makes SIGTERM and SIGINT requests for the current process to stop. The signal is
+/// forwarded to the child started by "spawn", if any, and "termination_requested" starts
+/// returning true so that the caller can wind down gracefully.
+///
+/// SIGINT is only forwarded to a child in a process group of its own. Otherwise, it shares
+/// the group of the current process and already got it from the terminal.
+#[cfg(unix)]
+pub fn forward_termination() -> Result<(), IOError> {
+    extern "C" fn handler(signal: libc::c_int) {
+        TERMINATED.store(true, Ordering::SeqCst);
+
+        let pid = CHILD.load(Ordering::SeqCst);
+        if pid < 0 || (pid > 0 && signal == libc::SIGTERM) {
+            unsafe { libc::kill(pid, signal) };
+        }
+    }
+
+    let mut action = unsafe { std::mem::zeroed::<libc::sigaction>() };
+    action.sa_sigaction = handler as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    action.sa_flags = libc::SA_RESTART;
+
+    for signal in [libc::SIGTERM, libc::SIGINT] {
+        if unsafe { libc::sigaction(signal, &action, std::ptr::null_mut()) } != 0 {
+            return Err(IOError::last_os_error());
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether the current process has been asked to stop. See "forward_termination".
+pub fn termination_requested() -> bool {
+    TERMINATED.load(Ordering::SeqCst)
+}
+
+#[cfg(windows)]
+fn exit_code(status: ExitStatus) -> i32 {
+    status.code().unwrap_or(1)
+}
+
+#[cfg(unix)]
+fn exit_code(status: ExitStatus) -> i32 {
+    use std::os::unix::process::ExitStatusExt;
+
+    match (status.code(), status.signal()) {
+        (Some(code), _) => code,
+        (None, Some(signal)) => 128 + signal,
+        (None, None) => 1,
+    }
+}
+
+#[cfg(unix)]
+#[derive(Debug, Clone, Copy)]
+pub enum Resource {
+    NoFile,
+    Core,
+    AddressSpace,
+    Cpu,
+}
+
+/// Sets both the soft and the hard limits of the given resource for the current
+/// process. The limits are inherited by any program it launches afterwards.
+#[cfg(unix)]
+pub fn setrlimit(resource: Resource, limit: u64) -> Result<(), IOError> {
+    let resource = match resource {
+        Resource::NoFile => libc::RLIMIT_NOFILE,
+        Resource::Core => libc::RLIMIT_CORE,
+        Resource::AddressSpace => libc::RLIMIT_AS,
+        Resource::Cpu => libc::RLIMIT_CPU,
+    };
+
+    let rlimit = libc::rlimit {
+        rlim_cur: limit as libc::rlim_t,
+        rlim_max: limit as libc::rlim_t,
+    };
+
+    match unsafe { libc::setrlimit(resource, &rlimit) } {
+        0 => Ok(()),
+        _ => Err(IOError::last_os_error()),
+    }
+}
+
+/// Tries to acquire an exclusive advisory lock on the given file descriptor without
+/// blocking. Returns false if the lock is already held by another open file description.
+#[cfg(unix)]
+pub fn try_lock(fd: i32) -> Result<bool, IOError> {
+    match unsafe { libc::flock(fd, libc::LOCK_EX | libc::LOCK_NB) } {
+        0 => Ok(true),
+        _ => match IOError::last_os_error() {
+            err if err.raw_os_error() == Some(libc::EWOULDBLOCK) => Ok(false),
+            err => Err(err),
+        },
+    }
+}
+
+/// Like "try_lock", but blocks until the lock can be acquired.
+#[cfg(unix)]
+pub fn lock(fd: i32) -> Result<(), IOError> {
+    match unsafe { libc::flock(fd, libc::LOCK_EX) } {
+        0 => Ok(()),
+        _ => Err(IOError::last_os_error()),
+    }
+}
+
+/// Clears the close-on-exec flag of the given file descriptor so that it is inherited by
+/// the programs launched afterwards.
+#[cfg(unix)]
+pub fn clear_cloexec(fd: i32) -> Result<(), IOError> {
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+    if flags < 0 {
+        return Err(IOError::last_os_error());
+    }
+
+    match unsafe { libc::fcntl(fd, libc::F_SETFD, flags & !libc::FD_CLOEXEC) } {
+        0 => Ok(()),
+        _ => Err(IOError::last_os_error()),
+    }
+}
+
+/// Detaches the current process from its controlling terminal using the classic double
+/// fork technique. It only returns in the grandchild, which is a new session member that
+/// cannot reacquire a controlling terminal. The intermediate child exits with code 0.
+///
+/// The original process waits until every copy of the returned pipe is closed and copies
+/// anything written to it to its own stderr. In that case, it exits with code 1 instead
+/// of 0. This lets the grandchild report the errors that happen while it starts up. The
+/// pipe is closed on "execve".
+#[cfg(unix)]
+pub fn daemonize() -> Result<std::fs::File, IOError> {
+    use std::io::{Read, Write};
+
+    let (mut reader, writer) = pipe()?;
+
+    match unsafe { libc::fork() } {
+        -1 => return Err(IOError::last_os_error()),
+        0 => drop(reader),
+        _ => {
+            drop(writer);
+
+            let mut report = Vec::new();
+            let _ = reader.read_to_end(&mut report);
+            let _ = std::io::stderr().write_all(&report);
+
+            unsafe { libc::_exit(i32::from(!report.is_empty())) };
+        }
+    }
+
+    if unsafe { libc::setsid() } == -1 {
+        return Err(IOError::last_os_error());
+    }
+
+    fork_and_exit_parent()?;
+
+    Ok(writer)
+}
+
+/// Creates a pipe whose ends are closed on "execve". Returns the read end first.
+#[cfg(unix)]
+fn pipe() -> Result<(std::fs::File, std::fs::File), IOError> {
+    use std::os::unix::io::FromRawFd;
+
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } == -1 {
+        return Err(IOError::last_os_error());
+    }
+
+    let (reader, writer) = unsafe {
+        (
+            std::fs::File::from_raw_fd(fds[0]),
+            std::fs::File::from_raw_fd(fds[1]),
+        )
+    };
+
+    for fd in fds {
+        if unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) } == -1 {
+            return Err(IOError::last_os_error());
+        }
+    }
+
+    Ok((reader, writer))
+}
+
+#[cfg(unix)]
+fn fork_and_exit_parent() -> Result<(), IOError> {
+    match unsafe { libc::fork() } {
+        -1 => Err(IOError::last_os_error()),
+        0 => Ok(()),
+        _ => unsafe { libc::_exit(0) },
+    }
+}
+
+/// Sets the file mode creation mask of the current process.
+#[cfg(unix)]
+pub fn umask(mode: u32) {
+    unsafe { libc::umask(mode as libc::mode_t) };
+}
+
+/// Closes all the file descriptors greater than or equal to "min". On Linux, this uses
+/// "close_range" when the kernel supports it and falls back to closing the descriptors
+/// listed under "/proc/self/fd" (or "/dev/fd" on other Unix systems) otherwise.
+#[cfg(unix)]
+pub fn close_fds_from(min: i32) -> Result<(), IOError> {
+    #[cfg(target_os = "linux")]
+    {
+        // The "close_range" syscall number is the same on all the supported Linux
+        // architectures but not all of them define "libc::SYS_close_range" yet.
+        const SYS_CLOSE_RANGE: libc::c_long = 436;
+
+        if unsafe { libc::syscall(SYS_CLOSE_RANGE, min as libc::c_uint, libc::c_uint::MAX, 0) } == 0
+        {
+            return Ok(());
+        }
+    }
+
+    let dir = match cfg!(target_os = "linux") {
+        true => "/proc/self/fd",
+        false => "/dev/fd",
+    };
+
+    // IMPORTANT: The descriptors must be collected before closing any of them because
+    // the directory stream itself is backed by a descriptor that shows up in the list.
+    let fds = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.file_name().to_str()?.parse::<i32>().ok())
+        .filter(|fd| *fd >= min)
+        .collect::<Vec<_>>();
+
+    for fd in fds {
+        unsafe { libc::close(fd) };
+    }
+
+    Ok(())
+}
+
+/// Makes the "target" file descriptor (e.g: 0 for stdin) refer to the same open file
+/// description as "source". Either way, "target" is inherited by executed programs.
+#[cfg(unix)]
+pub fn redirect_fd(source: i32, target: i32) -> Result<(), IOError> {
+    // "dup2" does nothing when both descriptors are the same, which would leave the
+    // close-on-exec flag of "source" as is.
+    if source == target {
+        let flags = unsafe { libc::fcntl(target, libc::F_GETFD) };
+
+        return match flags == -1
+            || unsafe { libc::fcntl(target, libc::F_SETFD, flags & !libc::FD_CLOEXEC) } == -1
+        {
+            true => Err(IOError::last_os_error()),
+            false => Ok(()),
+        };
+    }
+
+    match unsafe { libc::dup2(source, target) } {
+        -1 => Err(IOError::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+/// Moves the current process into new namespaces. When a new user namespace is created,
+/// the current user and group are mapped to themselves inside of it. Note that a new PID
+/// namespace only applies to the children of the current process.
+///
+/// IMPORTANT: The kernel refuses to create user namespaces for multi-threaded processes.
+#[cfg(target_os = "linux")]
+pub fn unshare(namespaces: &[crate::Namespace]) -> Result<(), IOError> {
+    use crate::Namespace;
+
+    let uid = unsafe { libc::getuid() };
+    let gid = unsafe { libc::getgid() };
+
+    let flags = namespaces.iter().fold(0, |flags, ns| {
+        flags
+            | match ns {
+                Namespace::User => libc::CLONE_NEWUSER,
+                Namespace::Mount => libc::CLONE_NEWNS,
+                Namespace::Pid => libc::CLONE_NEWPID,
+                Namespace::Net => libc::CLONE_NEWNET,
+            }
+    });
+
+    if unsafe { libc::unshare(flags) } != 0 {
+        return Err(IOError::last_os_error());
+    }
+
+    if flags & libc::CLONE_NEWUSER != 0 {
+        std::fs::write("/proc/self/setgroups", "deny")?;
+        std::fs::write("/proc/self/uid_map", format!("{} {} 1", uid, uid))?;
+        std::fs::write("/proc/self/gid_map", format!("{} {} 1", gid, gid))?;
+    }
+
+    // Prevent the mounts below from propagating back to the parent namespace.
+    if flags & libc::CLONE_NEWNS != 0 {
+        mount(None, "/", libc::MS_REC | libc::MS_PRIVATE)?;
+    }
+
+    Ok(())
+}
+
+/// Sets the "no_new_privs" bit of the current process. It is inherited across "execve"
+/// and by any child processes, and cannot be unset.
+#[cfg(target_os = "linux")]
+pub fn set_no_new_privs() -> Result<(), IOError> {
+    match unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } {
+        0 => Ok(()),
+        _ => Err(IOError::last_os_error()),
+    }
+}
+
+/// Bind mounts "source" on top of "target", optionally making it read-only. Requires
+/// the current process to be in its own mount namespace (or to be privileged).
+#[cfg(target_os = "linux")]
+pub fn bind_mount(source: &OsStr, target: &OsStr, read_only: bool) -> Result<(), IOError> {
+    mount(Some(source), target, libc::MS_BIND | libc::MS_REC)?;
+
+    if !read_only {
+        return Ok(());
+    }
+
+    // The kernel refuses to remount a bind mount inside a user namespace if any of the
+    // locked flags inherited from the original mount are dropped. Thus, keep them.
+    let c_target = CString::new(target.as_bytes())?;
+    let mut stat = unsafe { std::mem::zeroed::<libc::statvfs>() };
+
+    if unsafe { libc::statvfs(c_target.as_ptr(), &mut stat) } != 0 {
+        return Err(IOError::last_os_error());
+    }
+
+    let locked = [
+        (libc::ST_NOSUID, libc::MS_NOSUID),
+        (libc::ST_NODEV, libc::MS_NODEV),
+        (libc::ST_NOEXEC, libc::MS_NOEXEC),
+        (libc::ST_NOATIME, libc::MS_NOATIME),
+        (libc::ST_NODIRATIME, libc::MS_NODIRATIME),
+        (libc::ST_RELATIME, libc::MS_RELATIME),
+    ]
+    .iter()
+    .filter(|(st, _)| stat.f_flag & st != 0)
+    .fold(0, |flags, (_, ms)| flags | ms);
+
+    let flags = libc::MS_REMOUNT | libc::MS_BIND | libc::MS_RDONLY | locked;
+
+    mount(None, target, flags)
+}
+
+#[cfg(target_os = "linux")]
+fn mount<T>(source: Option<&OsStr>, target: T, flags: libc::c_ulong) -> Result<(), IOError>
+where
+    T: AsRef<OsStr>,
+{
+    let source = source.map(|s| CString::new(s.as_bytes())).transpose()?;
+    let target = CString::new(target.as_ref().as_bytes())?;
+
+    let source = source.as_ref().map_or(std::ptr::null(), |s| s.as_ptr());
+
+    match unsafe {
+        libc::mount(
+            source,
+            target.as_ptr(),
+            std::ptr::null(),
+            flags,
+            std::ptr::null(),
+        )
+    } {
+        0 => Ok(()),
+        _ => Err(IOError::last_os_error()),
+    }
+}
+
+/// Creates an anonymous file that lives in memory and is closed on "execve". Its content
+/// can be frozen via "seal" and executed via "fexecve".
+#[cfg(target_os = "linux")]
+pub fn memfd_create(name: &str) -> Result<std::fs::File, IOError> {
+    use std::os::unix::io::FromRawFd;
+
+    // Kernels that implement the "vm.memfd_noexec" sysctl may create non-executable
+    // memfds unless MFD_EXEC is given. Older kernels reject the flag as unknown.
+    const MFD_EXEC: libc::c_uint = 0x0010;
+
+    let name = CString::new(name)?;
+    let flags = libc::MFD_CLOEXEC | libc::MFD_ALLOW_SEALING;
+
+    let mut fd = unsafe { libc::memfd_create(name.as_ptr(), flags | MFD_EXEC) };
+
+    if fd < 0 && IOError::last_os_error().raw_os_error() == Some(libc::EINVAL) {
+        fd = unsafe { libc::memfd_create(name.as_ptr(), flags) };
+    }
+
+    match fd {
+        -1 => Err(IOError::last_os_error()),
+        fd => Ok(unsafe { std::fs::File::from_raw_fd(fd) }),
+    }
+}
+
+/// Prevents any further changes to the content of a file created via "memfd_create".
+#[cfg(target_os = "linux")]
+pub fn seal(fd: i32) -> Result<(), IOError> {
+    let seals = libc::F_SEAL_SHRINK | libc::F_SEAL_GROW | libc::F_SEAL_WRITE | libc::F_SEAL_SEAL;
+
+    match unsafe { libc::fcntl(fd, libc::F_ADD_SEALS, seals) } {
+        0 => Ok(()),
+        _ => Err(IOError::last_os_error()),
+    }
 }
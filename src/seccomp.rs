@@ -0,0 +1,948 @@
+// Copyright (c) 2023 The Nimbus Authors. All rights reserved.
+//
+// The use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+use crate::Seccomp;
+
+// See: https://github.com/torvalds/linux/blob/master/include/uapi/linux/audit.h
+const AUDIT_ARCH_X86_64: u32 = 0xC000_003E;
+const AUDIT_ARCH_AARCH64: u32 = 0xC000_00B7;
+
+// See: https://github.com/torvalds/linux/blob/master/include/uapi/linux/seccomp.h
+const SECCOMP_RET_KILL_PROCESS: u32 = 0x8000_0000;
+const SECCOMP_RET_ERRNO: u32 = 0x0005_0000;
+const SECCOMP_RET_ALLOW: u32 = 0x7FFF_0000;
+
+// See: https://github.com/torvalds/linux/blob/master/include/uapi/linux/bpf_common.h
+const BPF_LD_W_ABS: u16 = 0x20; // BPF_LD | BPF_W | BPF_ABS
+const BPF_JMP_JEQ_K: u16 = 0x15; // BPF_JMP | BPF_JEQ | BPF_K
+const BPF_JMP_JGE_K: u16 = 0x35; // BPF_JMP | BPF_JGE | BPF_K
+const BPF_RET_K: u16 = 0x06; // BPF_RET | BPF_K
+
+// The offsets of the "nr" and "arch" fields within "struct seccomp_data".
+const SECCOMP_DATA_NR: u32 = 0;
+const SECCOMP_DATA_ARCH: u32 = 4;
+
+// On x86_64, syscalls with this bit set belong to the x32 ABI which uses its own numbers.
+const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+
+/// The errno returned by the syscalls rejected by the filter.
+const EPERM: u32 = 1;
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Arch {
+    X86_64,
+    Aarch64,
+}
+
+impl Arch {
+    /// Returns the architecture of a Rust target triple (e.g: "x86_64-unknown-linux-gnu").
+    pub fn from_triple(triple: &str) -> Option<Self> {
+        match triple.split('-').next() {
+            Some("x86_64") => Some(Self::X86_64),
+            Some("aarch64") => Some(Self::Aarch64),
+            _ => None,
+        }
+    }
+
+    /// Returns the architecture the current process is running on.
+    pub fn current() -> Option<Self> {
+        Self::from_triple(std::env::consts::ARCH)
+    }
+
+    fn audit(&self) -> u32 {
+        match self {
+            Self::X86_64 => AUDIT_ARCH_X86_64,
+            Self::Aarch64 => AUDIT_ARCH_AARCH64,
+        }
+    }
+}
+
+/// A classic BPF instruction. Its memory layout matches the one of "struct sock_filter".
+#[repr(C)]
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Instruction {
+    pub code: u16,
+    pub jt: u8,
+    pub jf: u8,
+    pub k: u32,
+}
+
+impl Instruction {
+    fn stmt(code: u16, k: u32) -> Self {
+        Self {
+            code,
+            jt: 0,
+            jf: 0,
+            k,
+        }
+    }
+
+    fn jump(code: u16, k: u32, jt: u8, jf: u8) -> Self {
+        Self { code, jt, jf, k }
+    }
+}
+
+/// Returns the number of the syscall with the given name on the given architecture.
+pub fn syscall_number(name: &str, arch: Arch) -> Option<u32> {
+    SYSCALLS
+        .binary_search_by(|(n, _, _)| n.cmp(&name))
+        .ok()
+        .and_then(|i| match arch {
+            Arch::X86_64 => SYSCALLS[i].1,
+            Arch::Aarch64 => SYSCALLS[i].2,
+        })
+}
+
+/// Compiles the manifest "seccomp" section into a BPF program for the given architecture.
+/// Syscalls rejected by the program fail with EPERM. Programs running on a different
+/// architecture (or ABI) than the one the program was compiled for are killed.
+pub fn compile(seccomp: &Seccomp, arch: Arch) -> Result<Vec<Instruction>, String> {
+    let (names, matched, unmatched) = match (seccomp.allow.is_empty(), seccomp.deny.is_empty()) {
+        (false, true) => (&seccomp.allow, SECCOMP_RET_ALLOW, SECCOMP_RET_ERRNO | EPERM),
+        (true, false) => (&seccomp.deny, SECCOMP_RET_ERRNO | EPERM, SECCOMP_RET_ALLOW),
+        _ => {
+            return Err(String::from(
+                "exactly one of \"allow\" or \"deny\" must be set",
+            ))
+        }
+    };
+
+    let numbers = names
+        .iter()
+        .map(|n| syscall_number(n, arch).ok_or(format!("unknown syscall for {:?}: {}", arch, n)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut program = vec![
+        Instruction::stmt(BPF_LD_W_ABS, SECCOMP_DATA_ARCH),
+        Instruction::jump(BPF_JMP_JEQ_K, arch.audit(), 1, 0),
+        Instruction::stmt(BPF_RET_K, SECCOMP_RET_KILL_PROCESS),
+        Instruction::stmt(BPF_LD_W_ABS, SECCOMP_DATA_NR),
+    ];
+
+    if arch == Arch::X86_64 {
+        program.push(Instruction::jump(BPF_JMP_JGE_K, X32_SYSCALL_BIT, 0, 1));
+        program.push(Instruction::stmt(BPF_RET_K, SECCOMP_RET_KILL_PROCESS));
+    }
+
+    for number in numbers {
+        program.push(Instruction::jump(BPF_JMP_JEQ_K, number, 0, 1));
+        program.push(Instruction::stmt(BPF_RET_K, matched));
+    }
+
+    program.push(Instruction::stmt(BPF_RET_K, unmatched));
+
+    Ok(program)
+}
+
+/// Installs the BPF program as a seccomp filter for the current thread. The filter is
+/// inherited across "execve" and by any child processes. Unless the process has the
+/// CAP_SYS_ADMIN capability, the "no_new_privs" bit must have been set beforehand.
+#[cfg(target_os = "linux")]
+pub fn install(program: &[Instruction]) -> Result<(), std::io::Error> {
+    let prog = libc::sock_fprog {
+        len: program.len() as libc::c_ushort,
+        filter: program.as_ptr() as *mut libc::sock_filter,
+    };
+
+    match unsafe { libc::prctl(libc::PR_SET_SECCOMP, libc::SECCOMP_MODE_FILTER, &prog) } {
+        0 => Ok(()),
+        _ => Err(std::io::Error::last_os_error()),
+    }
+}
+
+// The syscall numbers for each of the supported architectures sorted by name.
+// See: https://github.com/torvalds/linux/blob/master/arch/x86/entry/syscalls/syscall_64.tbl
+// See: https://github.com/torvalds/linux/blob/master/include/uapi/asm-generic/unistd.h
+#[rustfmt::skip]
+static SYSCALLS: &[(&str, Option<u32>, Option<u32>)] = &[
+    ("_sysctl", Some(156), None),
+    ("accept", Some(43), Some(202)),
+    ("accept4", Some(288), Some(242)),
+    ("access", Some(21), None),
+    ("acct", Some(163), Some(89)),
+    ("add_key", Some(248), Some(217)),
+    ("adjtimex", Some(159), Some(171)),
+    ("afs_syscall", Some(183), None),
+    ("alarm", Some(37), None),
+    ("arch_prctl", Some(158), None),
+    ("bind", Some(49), Some(200)),
+    ("bpf", Some(321), Some(280)),
+    ("brk", Some(12), Some(214)),
+    ("capget", Some(125), Some(90)),
+    ("capset", Some(126), Some(91)),
+    ("chdir", Some(80), Some(49)),
+    ("chmod", Some(90), None),
+    ("chown", Some(92), None),
+    ("chroot", Some(161), Some(51)),
+    ("clock_adjtime", Some(305), Some(266)),
+    ("clock_getres", Some(229), Some(114)),
+    ("clock_gettime", Some(228), Some(113)),
+    ("clock_nanosleep", Some(230), Some(115)),
+    ("clock_settime", Some(227), Some(112)),
+    ("clone", Some(56), Some(220)),
+    ("clone3", Some(435), Some(435)),
+    ("close", Some(3), Some(57)),
+    ("close_range", Some(436), Some(436)),
+    ("connect", Some(42), Some(203)),
+    ("copy_file_range", Some(326), Some(285)),
+    ("creat", Some(85), None),
+    ("create_module", Some(174), None),
+    ("delete_module", Some(176), Some(106)),
+    ("dup", Some(32), Some(23)),
+    ("dup2", Some(33), None),
+    ("dup3", Some(292), Some(24)),
+    ("epoll_create", Some(213), None),
+    ("epoll_create1", Some(291), Some(20)),
+    ("epoll_ctl", Some(233), Some(21)),
+    ("epoll_ctl_old", Some(214), None),
+    ("epoll_pwait", Some(281), Some(22)),
+    ("epoll_pwait2", Some(441), Some(441)),
+    ("epoll_wait", Some(232), None),
+    ("epoll_wait_old", Some(215), None),
+    ("eventfd", Some(284), None),
+    ("eventfd2", Some(290), Some(19)),
+    ("execve", Some(59), Some(221)),
+    ("execveat", Some(322), Some(281)),
+    ("exit", Some(60), Some(93)),
+    ("exit_group", Some(231), Some(94)),
+    ("faccessat", Some(269), Some(48)),
+    ("faccessat2", Some(439), Some(439)),
+    ("fadvise64", Some(221), Some(223)),
+    ("fallocate", Some(285), Some(47)),
+    ("fanotify_init", Some(300), Some(262)),
+    ("fanotify_mark", Some(301), Some(263)),
+    ("fchdir", Some(81), Some(50)),
+    ("fchmod", Some(91), Some(52)),
+    ("fchmodat", Some(268), Some(53)),
+    ("fchown", Some(93), Some(55)),
+    ("fchownat", Some(260), Some(54)),
+    ("fcntl", Some(72), Some(25)),
+    ("fdatasync", Some(75), Some(83)),
+    ("fgetxattr", Some(193), Some(10)),
+    ("finit_module", Some(313), Some(273)),
+    ("flistxattr", Some(196), Some(13)),
+    ("flock", Some(73), Some(32)),
+    ("fork", Some(57), None),
+    ("fremovexattr", Some(199), Some(16)),
+    ("fsconfig", Some(431), Some(431)),
+    ("fsetxattr", Some(190), Some(7)),
+    ("fsmount", Some(432), Some(432)),
+    ("fsopen", Some(430), Some(430)),
+    ("fspick", Some(433), Some(433)),
+    ("fstat", Some(5), Some(80)),
+    ("fstatfs", Some(138), Some(44)),
+    ("fsync", Some(74), Some(82)),
+    ("ftruncate", Some(77), Some(46)),
+    ("futex", Some(202), Some(98)),
+    ("futex_waitv", Some(449), Some(449)),
+    ("futimesat", Some(261), None),
+    ("get_kernel_syms", Some(177), None),
+    ("get_mempolicy", Some(239), Some(236)),
+    ("get_robust_list", Some(274), Some(100)),
+    ("get_thread_area", Some(211), None),
+    ("getcpu", Some(309), Some(168)),
+    ("getcwd", Some(79), Some(17)),
+    ("getdents", Some(78), None),
+    ("getdents64", Some(217), Some(61)),
+    ("getegid", Some(108), Some(177)),
+    ("geteuid", Some(107), Some(175)),
+    ("getgid", Some(104), Some(176)),
+    ("getgroups", Some(115), Some(158)),
+    ("getitimer", Some(36), Some(102)),
+    ("getpeername", Some(52), Some(205)),
+    ("getpgid", Some(121), Some(155)),
+    ("getpgrp", Some(111), None),
+    ("getpid", Some(39), Some(172)),
+    ("getpmsg", Some(181), None),
+    ("getppid", Some(110), Some(173)),
+    ("getpriority", Some(140), Some(141)),
+    ("getrandom", Some(318), Some(278)),
+    ("getresgid", Some(120), Some(150)),
+    ("getresuid", Some(118), Some(148)),
+    ("getrlimit", Some(97), Some(163)),
+    ("getrusage", Some(98), Some(165)),
+    ("getsid", Some(124), Some(156)),
+    ("getsockname", Some(51), Some(204)),
+    ("getsockopt", Some(55), Some(209)),
+    ("gettid", Some(186), Some(178)),
+    ("gettimeofday", Some(96), Some(169)),
+    ("getuid", Some(102), Some(174)),
+    ("getxattr", Some(191), Some(8)),
+    ("init_module", Some(175), Some(105)),
+    ("inotify_add_watch", Some(254), Some(27)),
+    ("inotify_init", Some(253), None),
+    ("inotify_init1", Some(294), Some(26)),
+    ("inotify_rm_watch", Some(255), Some(28)),
+    ("io_cancel", Some(210), Some(3)),
+    ("io_destroy", Some(207), Some(1)),
+    ("io_getevents", Some(208), Some(4)),
+    ("io_setup", Some(206), Some(0)),
+    ("io_submit", Some(209), Some(2)),
+    ("io_uring_enter", Some(426), Some(426)),
+    ("io_uring_register", Some(427), Some(427)),
+    ("io_uring_setup", Some(425), Some(425)),
+    ("ioctl", Some(16), Some(29)),
+    ("ioperm", Some(173), None),
+    ("iopl", Some(172), None),
+    ("ioprio_get", Some(252), Some(31)),
+    ("ioprio_set", Some(251), Some(30)),
+    ("kcmp", Some(312), Some(272)),
+    ("kexec_file_load", Some(320), Some(294)),
+    ("kexec_load", Some(246), Some(104)),
+    ("keyctl", Some(250), Some(219)),
+    ("kill", Some(62), Some(129)),
+    ("landlock_add_rule", Some(445), Some(445)),
+    ("landlock_create_ruleset", Some(444), Some(444)),
+    ("landlock_restrict_self", Some(446), Some(446)),
+    ("lchown", Some(94), None),
+    ("lgetxattr", Some(192), Some(9)),
+    ("link", Some(86), None),
+    ("linkat", Some(265), Some(37)),
+    ("listen", Some(50), Some(201)),
+    ("listxattr", Some(194), Some(11)),
+    ("llistxattr", Some(195), Some(12)),
+    ("lookup_dcookie", Some(212), Some(18)),
+    ("lremovexattr", Some(198), Some(15)),
+    ("lseek", Some(8), Some(62)),
+    ("lsetxattr", Some(189), Some(6)),
+    ("lstat", Some(6), None),
+    ("madvise", Some(28), Some(233)),
+    ("mbind", Some(237), Some(235)),
+    ("membarrier", Some(324), Some(283)),
+    ("memfd_create", Some(319), Some(279)),
+    ("memfd_secret", Some(447), Some(447)),
+    ("migrate_pages", Some(256), Some(238)),
+    ("mincore", Some(27), Some(232)),
+    ("mkdir", Some(83), None),
+    ("mkdirat", Some(258), Some(34)),
+    ("mknod", Some(133), None),
+    ("mknodat", Some(259), Some(33)),
+    ("mlock", Some(149), Some(228)),
+    ("mlock2", Some(325), Some(284)),
+    ("mlockall", Some(151), Some(230)),
+    ("mmap", Some(9), Some(222)),
+    ("modify_ldt", Some(154), None),
+    ("mount", Some(165), Some(40)),
+    ("mount_setattr", Some(442), Some(442)),
+    ("move_mount", Some(429), Some(429)),
+    ("move_pages", Some(279), Some(239)),
+    ("mprotect", Some(10), Some(226)),
+    ("mq_getsetattr", Some(245), Some(185)),
+    ("mq_notify", Some(244), Some(184)),
+    ("mq_open", Some(240), Some(180)),
+    ("mq_timedreceive", Some(243), Some(183)),
+    ("mq_timedsend", Some(242), Some(182)),
+    ("mq_unlink", Some(241), Some(181)),
+    ("mremap", Some(25), Some(216)),
+    ("msgctl", Some(71), Some(187)),
+    ("msgget", Some(68), Some(186)),
+    ("msgrcv", Some(70), Some(188)),
+    ("msgsnd", Some(69), Some(189)),
+    ("msync", Some(26), Some(227)),
+    ("munlock", Some(150), Some(229)),
+    ("munlockall", Some(152), Some(231)),
+    ("munmap", Some(11), Some(215)),
+    ("name_to_handle_at", Some(303), Some(264)),
+    ("nanosleep", Some(35), Some(101)),
+    ("newfstatat", Some(262), Some(79)),
+    ("nfsservctl", Some(180), Some(42)),
+    ("open", Some(2), None),
+    ("open_by_handle_at", Some(304), Some(265)),
+    ("open_tree", Some(428), Some(428)),
+    ("openat", Some(257), Some(56)),
+    ("openat2", Some(437), Some(437)),
+    ("pause", Some(34), None),
+    ("perf_event_open", Some(298), Some(241)),
+    ("personality", Some(135), Some(92)),
+    ("pidfd_getfd", Some(438), Some(438)),
+    ("pidfd_open", Some(434), Some(434)),
+    ("pidfd_send_signal", Some(424), Some(424)),
+    ("pipe", Some(22), None),
+    ("pipe2", Some(293), Some(59)),
+    ("pivot_root", Some(155), Some(41)),
+    ("pkey_alloc", Some(330), Some(289)),
+    ("pkey_free", Some(331), Some(290)),
+    ("pkey_mprotect", Some(329), Some(288)),
+    ("poll", Some(7), None),
+    ("ppoll", Some(271), Some(73)),
+    ("prctl", Some(157), Some(167)),
+    ("pread64", Some(17), Some(67)),
+    ("preadv", Some(295), Some(69)),
+    ("preadv2", Some(327), Some(286)),
+    ("prlimit64", Some(302), Some(261)),
+    ("process_madvise", Some(440), Some(440)),
+    ("process_mrelease", Some(448), Some(448)),
+    ("process_vm_readv", Some(310), Some(270)),
+    ("process_vm_writev", Some(311), Some(271)),
+    ("pselect6", Some(270), Some(72)),
+    ("ptrace", Some(101), Some(117)),
+    ("putpmsg", Some(182), None),
+    ("pwrite64", Some(18), Some(68)),
+    ("pwritev", Some(296), Some(70)),
+    ("pwritev2", Some(328), Some(287)),
+    ("query_module", Some(178), None),
+    ("quotactl", Some(179), Some(60)),
+    ("quotactl_fd", Some(443), Some(443)),
+    ("read", Some(0), Some(63)),
+    ("readahead", Some(187), Some(213)),
+    ("readlink", Some(89), None),
+    ("readlinkat", Some(267), Some(78)),
+    ("readv", Some(19), Some(65)),
+    ("reboot", Some(169), Some(142)),
+    ("recvfrom", Some(45), Some(207)),
+    ("recvmmsg", Some(299), Some(243)),
+    ("recvmsg", Some(47), Some(212)),
+    ("remap_file_pages", Some(216), Some(234)),
+    ("removexattr", Some(197), Some(14)),
+    ("rename", Some(82), None),
+    ("renameat", Some(264), Some(38)),
+    ("renameat2", Some(316), Some(276)),
+    ("request_key", Some(249), Some(218)),
+    ("restart_syscall", Some(219), Some(128)),
+    ("rmdir", Some(84), None),
+    ("rseq", Some(334), Some(293)),
+    ("rt_sigaction", Some(13), Some(134)),
+    ("rt_sigpending", Some(127), Some(136)),
+    ("rt_sigprocmask", Some(14), Some(135)),
+    ("rt_sigqueueinfo", Some(129), Some(138)),
+    ("rt_sigreturn", Some(15), Some(139)),
+    ("rt_sigsuspend", Some(130), Some(133)),
+    ("rt_sigtimedwait", Some(128), Some(137)),
+    ("rt_tgsigqueueinfo", Some(297), Some(240)),
+    ("sched_get_priority_max", Some(146), Some(125)),
+    ("sched_get_priority_min", Some(147), Some(126)),
+    ("sched_getaffinity", Some(204), Some(123)),
+    ("sched_getattr", Some(315), Some(275)),
+    ("sched_getparam", Some(143), Some(121)),
+    ("sched_getscheduler", Some(145), Some(120)),
+    ("sched_rr_get_interval", Some(148), Some(127)),
+    ("sched_setaffinity", Some(203), Some(122)),
+    ("sched_setattr", Some(314), Some(274)),
+    ("sched_setparam", Some(142), Some(118)),
+    ("sched_setscheduler", Some(144), Some(119)),
+    ("sched_yield", Some(24), Some(124)),
+    ("seccomp", Some(317), Some(277)),
+    ("security", Some(185), None),
+    ("select", Some(23), None),
+    ("semctl", Some(66), Some(191)),
+    ("semget", Some(64), Some(190)),
+    ("semop", Some(65), Some(193)),
+    ("semtimedop", Some(220), Some(192)),
+    ("sendfile", Some(40), Some(71)),
+    ("sendmmsg", Some(307), Some(269)),
+    ("sendmsg", Some(46), Some(211)),
+    ("sendto", Some(44), Some(206)),
+    ("set_mempolicy", Some(238), Some(237)),
+    ("set_mempolicy_home_node", Some(450), Some(450)),
+    ("set_robust_list", Some(273), Some(99)),
+    ("set_thread_area", Some(205), None),
+    ("set_tid_address", Some(218), Some(96)),
+    ("setdomainname", Some(171), Some(162)),
+    ("setfsgid", Some(123), Some(152)),
+    ("setfsuid", Some(122), Some(151)),
+    ("setgid", Some(106), Some(144)),
+    ("setgroups", Some(116), Some(159)),
+    ("sethostname", Some(170), Some(161)),
+    ("setitimer", Some(38), Some(103)),
+    ("setns", Some(308), Some(268)),
+    ("setpgid", Some(109), Some(154)),
+    ("setpriority", Some(141), Some(140)),
+    ("setregid", Some(114), Some(143)),
+    ("setresgid", Some(119), Some(149)),
+    ("setresuid", Some(117), Some(147)),
+    ("setreuid", Some(113), Some(145)),
+    ("setrlimit", Some(160), Some(164)),
+    ("setsid", Some(112), Some(157)),
+    ("setsockopt", Some(54), Some(208)),
+    ("settimeofday", Some(164), Some(170)),
+    ("setuid", Some(105), Some(146)),
+    ("setxattr", Some(188), Some(5)),
+    ("shmat", Some(30), Some(196)),
+    ("shmctl", Some(31), Some(195)),
+    ("shmdt", Some(67), Some(197)),
+    ("shmget", Some(29), Some(194)),
+    ("shutdown", Some(48), Some(210)),
+    ("sigaltstack", Some(131), Some(132)),
+    ("signalfd", Some(282), None),
+    ("signalfd4", Some(289), Some(74)),
+    ("socket", Some(41), Some(198)),
+    ("socketpair", Some(53), Some(199)),
+    ("splice", Some(275), Some(76)),
+    ("stat", Some(4), None),
+    ("statfs", Some(137), Some(43)),
+    ("statx", Some(332), Some(291)),
+    ("swapoff", Some(168), Some(225)),
+    ("swapon", Some(167), Some(224)),
+    ("symlink", Some(88), None),
+    ("symlinkat", Some(266), Some(36)),
+    ("sync", Some(162), Some(81)),
+    ("sync_file_range", Some(277), Some(84)),
+    ("syncfs", Some(306), Some(267)),
+    ("sysfs", Some(139), None),
+    ("sysinfo", Some(99), Some(179)),
+    ("syslog", Some(103), Some(116)),
+    ("tee", Some(276), Some(77)),
+    ("tgkill", Some(234), Some(131)),
+    ("time", Some(201), None),
+    ("timer_create", Some(222), Some(107)),
+    ("timer_delete", Some(226), Some(111)),
+    ("timer_getoverrun", Some(225), Some(109)),
+    ("timer_gettime", Some(224), Some(108)),
+    ("timer_settime", Some(223), Some(110)),
+    ("timerfd_create", Some(283), Some(85)),
+    ("timerfd_gettime", Some(287), Some(87)),
+    ("timerfd_settime", Some(286), Some(86)),
+    ("times", Some(100), Some(153)),
+    ("tkill", Some(200), Some(130)),
+    ("truncate", Some(76), Some(45)),
+    ("tuxcall", Some(184), None),
+    ("umask", Some(95), Some(166)),
+    ("umount2", Some(166), Some(39)),
+    ("uname", Some(63), Some(160)),
+    ("unlink", Some(87), None),
+    ("unlinkat", Some(263), Some(35)),
+    ("unshare", Some(272), Some(97)),
+    ("uselib", Some(134), None),
+    ("userfaultfd", Some(323), Some(282)),
+    ("ustat", Some(136), None),
+    ("utime", Some(132), None),
+    ("utimensat", Some(280), Some(88)),
+    ("utimes", Some(235), None),
+    ("vfork", Some(58), None),
+    ("vhangup", Some(153), Some(58)),
+    ("vmsplice", Some(278), Some(75)),
+    ("vserver", Some(236), None),
+    ("wait4", Some(61), Some(260)),
+    ("waitid", Some(247), Some(95)),
+    ("write", Some(1), Some(64)),
+    ("writev", Some(20), Some(66)),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn syscalls_sorted_by_name() {
+        assert!(SYSCALLS.windows(2).all(|w| w[0].0 < w[1].0));
+    }
+
+    // The syscall numbers defined by "include/uapi/asm-generic/unistd.h" (Linux 6.1) for
+    // 64-bit architectures, which aarch64 uses as is.
+    #[rustfmt::skip]
+    static ASM_GENERIC: &[(&str, u32)] = &[
+        ("io_setup", 0),
+        ("io_destroy", 1),
+        ("io_submit", 2),
+        ("io_cancel", 3),
+        ("io_getevents", 4),
+        ("setxattr", 5),
+        ("lsetxattr", 6),
+        ("fsetxattr", 7),
+        ("getxattr", 8),
+        ("lgetxattr", 9),
+        ("fgetxattr", 10),
+        ("listxattr", 11),
+        ("llistxattr", 12),
+        ("flistxattr", 13),
+        ("removexattr", 14),
+        ("lremovexattr", 15),
+        ("fremovexattr", 16),
+        ("getcwd", 17),
+        ("lookup_dcookie", 18),
+        ("eventfd2", 19),
+        ("epoll_create1", 20),
+        ("epoll_ctl", 21),
+        ("epoll_pwait", 22),
+        ("dup", 23),
+        ("dup3", 24),
+        ("fcntl", 25),
+        ("inotify_init1", 26),
+        ("inotify_add_watch", 27),
+        ("inotify_rm_watch", 28),
+        ("ioctl", 29),
+        ("ioprio_set", 30),
+        ("ioprio_get", 31),
+        ("flock", 32),
+        ("mknodat", 33),
+        ("mkdirat", 34),
+        ("unlinkat", 35),
+        ("symlinkat", 36),
+        ("linkat", 37),
+        ("renameat", 38),
+        ("umount2", 39),
+        ("mount", 40),
+        ("pivot_root", 41),
+        ("nfsservctl", 42),
+        ("statfs", 43),
+        ("fstatfs", 44),
+        ("truncate", 45),
+        ("ftruncate", 46),
+        ("fallocate", 47),
+        ("faccessat", 48),
+        ("chdir", 49),
+        ("fchdir", 50),
+        ("chroot", 51),
+        ("fchmod", 52),
+        ("fchmodat", 53),
+        ("fchownat", 54),
+        ("fchown", 55),
+        ("openat", 56),
+        ("close", 57),
+        ("vhangup", 58),
+        ("pipe2", 59),
+        ("quotactl", 60),
+        ("getdents64", 61),
+        ("lseek", 62),
+        ("read", 63),
+        ("write", 64),
+        ("readv", 65),
+        ("writev", 66),
+        ("pread64", 67),
+        ("pwrite64", 68),
+        ("preadv", 69),
+        ("pwritev", 70),
+        ("sendfile", 71),
+        ("pselect6", 72),
+        ("ppoll", 73),
+        ("signalfd4", 74),
+        ("vmsplice", 75),
+        ("splice", 76),
+        ("tee", 77),
+        ("readlinkat", 78),
+        ("newfstatat", 79),
+        ("fstat", 80),
+        ("sync", 81),
+        ("fsync", 82),
+        ("fdatasync", 83),
+        ("sync_file_range", 84),
+        ("sync_file_range2", 84),
+        ("timerfd_create", 85),
+        ("timerfd_settime", 86),
+        ("timerfd_gettime", 87),
+        ("utimensat", 88),
+        ("acct", 89),
+        ("capget", 90),
+        ("capset", 91),
+        ("personality", 92),
+        ("exit", 93),
+        ("exit_group", 94),
+        ("waitid", 95),
+        ("set_tid_address", 96),
+        ("unshare", 97),
+        ("futex", 98),
+        ("set_robust_list", 99),
+        ("get_robust_list", 100),
+        ("nanosleep", 101),
+        ("getitimer", 102),
+        ("setitimer", 103),
+        ("kexec_load", 104),
+        ("init_module", 105),
+        ("delete_module", 106),
+        ("timer_create", 107),
+        ("timer_gettime", 108),
+        ("timer_getoverrun", 109),
+        ("timer_settime", 110),
+        ("timer_delete", 111),
+        ("clock_settime", 112),
+        ("clock_gettime", 113),
+        ("clock_getres", 114),
+        ("clock_nanosleep", 115),
+        ("syslog", 116),
+        ("ptrace", 117),
+        ("sched_setparam", 118),
+        ("sched_setscheduler", 119),
+        ("sched_getscheduler", 120),
+        ("sched_getparam", 121),
+        ("sched_setaffinity", 122),
+        ("sched_getaffinity", 123),
+        ("sched_yield", 124),
+        ("sched_get_priority_max", 125),
+        ("sched_get_priority_min", 126),
+        ("sched_rr_get_interval", 127),
+        ("restart_syscall", 128),
+        ("kill", 129),
+        ("tkill", 130),
+        ("tgkill", 131),
+        ("sigaltstack", 132),
+        ("rt_sigsuspend", 133),
+        ("rt_sigaction", 134),
+        ("rt_sigprocmask", 135),
+        ("rt_sigpending", 136),
+        ("rt_sigtimedwait", 137),
+        ("rt_sigqueueinfo", 138),
+        ("rt_sigreturn", 139),
+        ("setpriority", 140),
+        ("getpriority", 141),
+        ("reboot", 142),
+        ("setregid", 143),
+        ("setgid", 144),
+        ("setreuid", 145),
+        ("setuid", 146),
+        ("setresuid", 147),
+        ("getresuid", 148),
+        ("setresgid", 149),
+        ("getresgid", 150),
+        ("setfsuid", 151),
+        ("setfsgid", 152),
+        ("times", 153),
+        ("setpgid", 154),
+        ("getpgid", 155),
+        ("getsid", 156),
+        ("setsid", 157),
+        ("getgroups", 158),
+        ("setgroups", 159),
+        ("uname", 160),
+        ("sethostname", 161),
+        ("setdomainname", 162),
+        ("getrlimit", 163),
+        ("setrlimit", 164),
+        ("getrusage", 165),
+        ("umask", 166),
+        ("prctl", 167),
+        ("getcpu", 168),
+        ("gettimeofday", 169),
+        ("settimeofday", 170),
+        ("adjtimex", 171),
+        ("getpid", 172),
+        ("getppid", 173),
+        ("getuid", 174),
+        ("geteuid", 175),
+        ("getgid", 176),
+        ("getegid", 177),
+        ("gettid", 178),
+        ("sysinfo", 179),
+        ("mq_open", 180),
+        ("mq_unlink", 181),
+        ("mq_timedsend", 182),
+        ("mq_timedreceive", 183),
+        ("mq_notify", 184),
+        ("mq_getsetattr", 185),
+        ("msgget", 186),
+        ("msgctl", 187),
+        ("msgrcv", 188),
+        ("msgsnd", 189),
+        ("semget", 190),
+        ("semctl", 191),
+        ("semtimedop", 192),
+        ("semop", 193),
+        ("shmget", 194),
+        ("shmctl", 195),
+        ("shmat", 196),
+        ("shmdt", 197),
+        ("socket", 198),
+        ("socketpair", 199),
+        ("bind", 200),
+        ("listen", 201),
+        ("accept", 202),
+        ("connect", 203),
+        ("getsockname", 204),
+        ("getpeername", 205),
+        ("sendto", 206),
+        ("recvfrom", 207),
+        ("setsockopt", 208),
+        ("getsockopt", 209),
+        ("shutdown", 210),
+        ("sendmsg", 211),
+        ("recvmsg", 212),
+        ("readahead", 213),
+        ("brk", 214),
+        ("munmap", 215),
+        ("mremap", 216),
+        ("add_key", 217),
+        ("request_key", 218),
+        ("keyctl", 219),
+        ("clone", 220),
+        ("execve", 221),
+        ("mmap", 222),
+        ("fadvise64", 223),
+        ("swapon", 224),
+        ("swapoff", 225),
+        ("mprotect", 226),
+        ("msync", 227),
+        ("mlock", 228),
+        ("munlock", 229),
+        ("mlockall", 230),
+        ("munlockall", 231),
+        ("mincore", 232),
+        ("madvise", 233),
+        ("remap_file_pages", 234),
+        ("mbind", 235),
+        ("get_mempolicy", 236),
+        ("set_mempolicy", 237),
+        ("migrate_pages", 238),
+        ("move_pages", 239),
+        ("rt_tgsigqueueinfo", 240),
+        ("perf_event_open", 241),
+        ("accept4", 242),
+        ("recvmmsg", 243),
+        ("arch_specific_syscall", 244),
+        ("wait4", 260),
+        ("prlimit64", 261),
+        ("fanotify_init", 262),
+        ("fanotify_mark", 263),
+        ("name_to_handle_at", 264),
+        ("open_by_handle_at", 265),
+        ("clock_adjtime", 266),
+        ("syncfs", 267),
+        ("setns", 268),
+        ("sendmmsg", 269),
+        ("process_vm_readv", 270),
+        ("process_vm_writev", 271),
+        ("kcmp", 272),
+        ("finit_module", 273),
+        ("sched_setattr", 274),
+        ("sched_getattr", 275),
+        ("renameat2", 276),
+        ("seccomp", 277),
+        ("getrandom", 278),
+        ("memfd_create", 279),
+        ("bpf", 280),
+        ("execveat", 281),
+        ("userfaultfd", 282),
+        ("membarrier", 283),
+        ("mlock2", 284),
+        ("copy_file_range", 285),
+        ("preadv2", 286),
+        ("pwritev2", 287),
+        ("pkey_mprotect", 288),
+        ("pkey_alloc", 289),
+        ("pkey_free", 290),
+        ("statx", 291),
+        ("io_pgetevents", 292),
+        ("rseq", 293),
+        ("kexec_file_load", 294),
+        ("clock_gettime64", 403),
+        ("clock_settime64", 404),
+        ("clock_adjtime64", 405),
+        ("clock_getres_time64", 406),
+        ("clock_nanosleep_time64", 407),
+        ("timer_gettime64", 408),
+        ("timer_settime64", 409),
+        ("timerfd_gettime64", 410),
+        ("timerfd_settime64", 411),
+        ("utimensat_time64", 412),
+        ("pselect6_time64", 413),
+        ("ppoll_time64", 414),
+        ("io_pgetevents_time64", 416),
+        ("recvmmsg_time64", 417),
+        ("mq_timedsend_time64", 418),
+        ("mq_timedreceive_time64", 419),
+        ("semtimedop_time64", 420),
+        ("rt_sigtimedwait_time64", 421),
+        ("futex_time64", 422),
+        ("sched_rr_get_interval_time64", 423),
+        ("pidfd_send_signal", 424),
+        ("io_uring_setup", 425),
+        ("io_uring_enter", 426),
+        ("io_uring_register", 427),
+        ("open_tree", 428),
+        ("move_mount", 429),
+        ("fsopen", 430),
+        ("fsconfig", 431),
+        ("fsmount", 432),
+        ("fspick", 433),
+        ("pidfd_open", 434),
+        ("clone3", 435),
+        ("close_range", 436),
+        ("openat2", 437),
+        ("pidfd_getfd", 438),
+        ("faccessat2", 439),
+        ("process_madvise", 440),
+        ("epoll_pwait2", 441),
+        ("mount_setattr", 442),
+        ("quotactl_fd", 443),
+        ("landlock_create_ruleset", 444),
+        ("landlock_add_rule", 445),
+        ("landlock_restrict_self", 446),
+        ("memfd_secret", 447),
+        ("process_mrelease", 448),
+        ("futex_waitv", 449),
+        ("set_mempolicy_home_node", 450),
+    ];
+
+    #[test]
+    fn syscalls_when_aarch64_then_match_asm_generic() {
+        for (name, _, aarch64) in SYSCALLS {
+            let generic = ASM_GENERIC
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, nr)| *nr);
+
+            assert_eq!(*aarch64, generic, "aarch64 number of {}", name);
+        }
+    }
+
+    #[test]
+    fn syscall_number_when_known_then_succeeds() {
+        assert_eq!(syscall_number("read", Arch::X86_64), Some(0));
+        assert_eq!(syscall_number("read", Arch::Aarch64), Some(63));
+        assert_eq!(syscall_number("ptrace", Arch::X86_64), Some(101));
+        assert_eq!(syscall_number("ptrace", Arch::Aarch64), Some(117));
+    }
+
+    #[test]
+    fn syscall_number_when_unknown_then_fails() {
+        assert_eq!(syscall_number("not_a_syscall", Arch::X86_64), None);
+        assert_eq!(syscall_number("open", Arch::Aarch64), None);
+    }
+
+    #[test]
+    fn compile_when_deny_list_then_rejects_listed() {
+        let seccomp = Seccomp {
+            allow: Vec::new(),
+            deny: vec![String::from("ptrace"), String::from("mount")],
+        };
+
+        let program = compile(&seccomp, Arch::Aarch64).unwrap();
+
+        assert_eq!(
+            program[4..],
+            [
+                Instruction::jump(BPF_JMP_JEQ_K, 117, 0, 1),
+                Instruction::stmt(BPF_RET_K, SECCOMP_RET_ERRNO | EPERM),
+                Instruction::jump(BPF_JMP_JEQ_K, 40, 0, 1),
+                Instruction::stmt(BPF_RET_K, SECCOMP_RET_ERRNO | EPERM),
+                Instruction::stmt(BPF_RET_K, SECCOMP_RET_ALLOW),
+            ]
+        );
+    }
+
+    #[test]
+    fn compile_when_allow_list_then_rejects_unlisted() {
+        let seccomp = Seccomp {
+            allow: vec![String::from("read")],
+            deny: Vec::new(),
+        };
+
+        let program = compile(&seccomp, Arch::X86_64).unwrap();
+
+        assert_eq!(
+            program[6..],
+            [
+                Instruction::jump(BPF_JMP_JEQ_K, 0, 0, 1),
+                Instruction::stmt(BPF_RET_K, SECCOMP_RET_ALLOW),
+                Instruction::stmt(BPF_RET_K, SECCOMP_RET_ERRNO | EPERM),
+            ]
+        );
+    }
+
+    #[test]
+    fn compile_when_not_valid_then_fails() {
+        let both = Seccomp {
+            allow: vec![String::from("read")],
+            deny: vec![String::from("write")],
+        };
+
+        let unknown = Seccomp {
+            allow: Vec::new(),
+            deny: vec![String::from("not_a_syscall")],
+        };
+
+        assert!(compile(&Seccomp::default(), Arch::X86_64).is_err());
+        assert!(compile(&both, Arch::X86_64).is_err());
+        assert!(compile(&unknown, Arch::X86_64).is_err());
+    }
+}
@@ -0,0 +1,243 @@
+// Copyright (c) 2023 The Nimbus Authors. All rights reserved.
+//
+// The use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+use regex::{Captures, Regex};
+use std::{
+    collections::HashMap,
+    error::Error,
+    ffi::{OsStr, OsString},
+    path::{Path, PathBuf},
+};
+
+pub type SubstitutionFn<'a> = dyn Fn(&str) -> String + 'a;
+
+pub fn apply<T>(input: &OsStr, substitutions: &[T]) -> OsString
+where
+    T: Fn(&str) -> String,
+{
+    let input_str = match input.to_str() {
+        Some(x) => x,
+        None => return input.to_owned(),
+    };
+
+    let mut result = String::from(input_str);
+    for substitution in substitutions {
+        result = substitution(&result)
+    }
+
+    OsString::from(result)
+}
+
+pub fn current_exe<'a>(path: &'a Path) -> Result<Box<SubstitutionFn<'a>>, Box<dyn Error>> {
+    let re = Regex::new(r"\{kickoff.self.path\}")?;
+
+    let path = path
+        .to_str()
+        .ok_or("the current executable path is not convertible to UTF-8")?;
+
+    let closure = move |x: &str| -> String { re.replace_all(x, path).to_string() };
+
+    Ok(Box::new(closure))
+}
+
+pub fn current_dir<'a>(path: &'a Path) -> Result<Box<SubstitutionFn<'a>>, Box<dyn Error>> {
+    let re = Regex::new(r"\{kickoff.self.dir\}")?;
+
+    let path = path
+        .to_str()
+        .ok_or("the current executable directory is not convertible to UTF-8")?;
+
+    let closure = move |x: &str| -> String { re.replace_all(x, path).to_string() };
+
+    Ok(Box::new(closure))
+}
+
+/// Replaces "{kickoff.payload.<name>}" with the directory where the payload with that
+/// name has been extracted to. References to unknown payloads are left untouched.
+pub fn payload_paths<'a>(
+    payloads: &'a HashMap<String, PathBuf>,
+) -> Result<Box<SubstitutionFn<'a>>, Box<dyn Error>> {
+    let re = Regex::new(r"\{kickoff.payload.([A-Za-z0-9_-]+)\}")?;
+
+    let payloads = payloads
+        .iter()
+        .map(|(name, path)| match path.to_str() {
+            Some(p) => Ok((name.as_str(), p)),
+            None => Err(format!(
+                "the \"{}\" payload path is not convertible to UTF-8",
+                name
+            )),
+        })
+        .collect::<Result<HashMap<_, _>, _>>()?;
+
+    let closure = move |x: &str| -> String {
+        re.replace_all(x, |caps: &Captures| match payloads.get(&caps[1]) {
+            Some(path) => path.to_string(),
+            None => caps[0].to_string(),
+        })
+        .to_string()
+    };
+
+    Ok(Box::new(closure))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(unix)]
+    fn apply_when_input_not_utf8_then_noop() {
+        use std::os::unix::prelude::OsStringExt;
+
+        let input = OsString::from_vec(vec![0xC3, 0x28]); // Invalid UTF-8 sequence.
+        let subs = vec![|_: &str| -> String { String::new() }];
+
+        assert_eq!(apply(&input, &subs), input);
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn apply_when_input_not_utf16_then_noop() {
+        use std::os::windows::ffi::OsStringExt;
+
+        let input = OsString::from_wide(vec![0x0066, 0x006F, 0xD800, 0x006F]); // Invalid UTF-16 sequence.
+        let subs = vec![|_: &str| -> String { String::new() }];
+
+        assert_eq!(apply(&input, &subs), input);
+    }
+
+    #[test]
+    fn apply_when_substitutions_not_match_then_noop() {
+        let input = OsString::from("some {var} input");
+
+        let subs = vec![
+            |x: &str| -> String {
+                let re = Regex::new(r"non-matching-one").unwrap();
+                re.replace_all(x, "interpolated").to_string()
+            },
+            |x: &str| -> String {
+                let re = Regex::new(r"non-matching-two").unwrap();
+                re.replace_all(x, "interpolated").to_string()
+            },
+        ];
+
+        assert_eq!(apply(&input, &subs), "some {var} input")
+    }
+
+    #[test]
+    fn apply_when_substitutions_match_then_substitutes() {
+        let input = OsString::from("both {some} and {other} are interpolated");
+
+        let subs = vec![
+            |x: &str| -> String {
+                let re = Regex::new(r"\{some\}").unwrap();
+                re.replace_all(x, "first-value").to_string()
+            },
+            |x: &str| -> String {
+                let re = Regex::new(r"\{other\}").unwrap();
+                re.replace_all(x, "second-value").to_string()
+            },
+            |x: &str| -> String {
+                let re = Regex::new(r"\{non-matching\}").unwrap();
+                re.replace_all(x, "third-value").to_string()
+            },
+        ];
+
+        assert_eq!(
+            apply(&input, &subs),
+            "both first-value and second-value are interpolated"
+        )
+    }
+
+    #[test]
+    fn current_exe_when_input_contains_pattern_then_substitutes() {
+        let path = PathBuf::from("/some/path");
+        let func = current_exe(&path).unwrap();
+
+        assert_eq!(func("{kickoff.self.path}"), "/some/path");
+
+        assert_eq!(
+            func("the path is {kickoff.self.path}"),
+            "the path is /some/path"
+        );
+
+        assert_eq!(
+            func("{do.not.match} {kickoff.self.path}"),
+            "{do.not.match} /some/path"
+        );
+
+        assert_eq!(
+            func("one: {kickoff.self.path} two: {kickoff.self.path}"),
+            "one: /some/path two: /some/path"
+        )
+    }
+
+    #[test]
+    fn current_exe_when_input_not_contains_pattern_then_noop() {
+        let path = PathBuf::from("/some/path");
+        let func = current_exe(&path).unwrap();
+
+        assert_eq!(func("does not contain pattern"), "does not contain pattern");
+        assert_eq!(func("{do.not.match}"), "{do.not.match}");
+    }
+
+    #[test]
+    fn current_dir_when_input_contains_pattern_then_substitutes() {
+        let path = PathBuf::from("/some/path");
+        let func = current_dir(&path).unwrap();
+
+        assert_eq!(func("{kickoff.self.dir}"), "/some/path");
+
+        assert_eq!(
+            func("the path is {kickoff.self.dir}"),
+            "the path is /some/path"
+        );
+
+        assert_eq!(
+            func("{do.not.match} {kickoff.self.dir}"),
+            "{do.not.match} /some/path"
+        );
+
+        assert_eq!(
+            func("one: {kickoff.self.dir} two: {kickoff.self.dir}"),
+            "one: /some/path two: /some/path"
+        )
+    }
+
+    #[test]
+    fn current_dir_when_input_not_contains_pattern_then_noop() {
+        let path = PathBuf::from("/some/path");
+        let func = current_dir(&path).unwrap();
+
+        assert_eq!(func("does not contain pattern"), "does not contain pattern");
+        assert_eq!(func("{do.not.match}"), "{do.not.match}");
+    }
+
+    #[test]
+    fn payload_paths_when_input_contains_pattern_then_substitutes() {
+        let payloads = HashMap::from([
+            (String::from("app"), PathBuf::from("/cache/app")),
+            (String::from("lib-2"), PathBuf::from("/cache/lib")),
+        ]);
+        let func = payload_paths(&payloads).unwrap();
+
+        assert_eq!(func("{kickoff.payload.app}/main.py"), "/cache/app/main.py");
+
+        assert_eq!(
+            func("{kickoff.payload.lib-2}:{kickoff.payload.app}"),
+            "/cache/lib:/cache/app"
+        );
+    }
+
+    #[test]
+    fn payload_paths_when_payload_unknown_then_noop() {
+        let payloads = HashMap::from([(String::from("app"), PathBuf::from("/cache/app"))]);
+        let func = payload_paths(&payloads).unwrap();
+
+        assert_eq!(func("{kickoff.payload.other}"), "{kickoff.payload.other}");
+        assert_eq!(func("{kickoff.self.dir}"), "{kickoff.self.dir}");
+    }
+}